@@ -51,7 +51,7 @@ fn main() -> Result<()> {
     ens160.initialize().unwrap();
 
     // optional: usually not required
-    ens160.set_temp_rh_comp(21.5, 41).unwrap();
+    ens160.set_temp_rh_comp(21.5, 41.0).unwrap();
     let (temp_c, rh) = ens160.get_temp_rh_comp().unwrap();
     info!(
         "compensation set to temperature = {} C, relative humidity = {} %",
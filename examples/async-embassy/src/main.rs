@@ -82,7 +82,7 @@ async fn main(_spawner: Spawner) {
     ens160.initialize().await.unwrap();
 
     // optional: usually not required
-    ens160.set_temp_rh_comp(21.5, 41).await.unwrap();
+    ens160.set_temp_rh_comp(21.5, 41.0).await.unwrap();
     Timer::after((Duration::from_secs(1)));
     let (temp_c, rh) = ens160.get_temp_rh_comp().await.unwrap();
     info!(
@@ -28,7 +28,7 @@ fn main() -> Result<()> {
     let delayer = Delay {};
 
     let mut ens160 = Ens160::new_secondary_address(dev_i2c, delayer);
-    ens160.set_temp_rh_comp(21.5, 41).unwrap();
+    ens160.set_temp_rh_comp(21.5, 41.0).unwrap();
     let ens160_result = ens160.initialize();
     match ens160_result {
         Ok(what) => info!("ENS160 initialized ok: {}", what),
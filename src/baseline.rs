@@ -0,0 +1,261 @@
+// host side clean air baseline of the hot plate resistances, with a state blob for flash storage
+
+use crate::data::HOT_PLATES;
+
+/// size in bytes of the saved BaselineEstimator state
+pub const BASELINE_STATE_SIZE: usize = 23;
+
+const STATE_MAGIC: u8 = 0xb1;
+const STATE_VERSION: u8 = 1;
+
+/// number of updates before a fresh estimator reports a settled baseline
+pub const DEFAULT_BURN_IN_SAMPLES: u32 = 720;
+
+/// Errors when restoring a saved baseline state
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BaselineStateError {
+    /// state blob is shorter than BASELINE_STATE_SIZE
+    BadLength(usize),
+    /// state blob was not written by BaselineEstimator::save()
+    BadMagic(u8),
+    /// state blob was written by an unsupported format version
+    UnsupportedVersion(u8),
+    /// state blob checksum does not match, flash contents are corrupted
+    BadChecksum,
+}
+
+/// Estimates the long term clean air resistance of each hot plate.
+/// The ENS160 loses its internal baseline on every power cycle, this estimator can be
+/// saved with save() and restored at boot with restore() so no fresh burn-in is needed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BaselineEstimator {
+    baselines_ohm: [f32; HOT_PLATES],
+    samples: u32,
+    burn_in_samples: u32,
+    rise_alpha: f32,
+    fall_alpha: f32,
+}
+
+impl Default for BaselineEstimator {
+    fn default() -> Self {
+        Self::new(0.1, 0.001)
+    }
+}
+
+impl BaselineEstimator {
+    /// create a new estimator without a baseline, the first update sets it.
+    /// rise_alpha and fall_alpha (0.0 to 1.0) are the per update filter weights used when
+    /// the resistance is above (cleaner air) or below (polluted air) the baseline
+    pub fn new(rise_alpha: f32, fall_alpha: f32) -> Self {
+        BaselineEstimator {
+            baselines_ohm: [0.0; HOT_PLATES],
+            samples: 0,
+            burn_in_samples: DEFAULT_BURN_IN_SAMPLES,
+            rise_alpha,
+            fall_alpha,
+        }
+    }
+
+    /// create an estimator seeded from factory calibration (clean air resistances in ohms),
+    /// it is treated as settled right away
+    pub fn from_factory(baselines_ohm: [f32; HOT_PLATES]) -> Self {
+        let mut estimator = Self::default();
+        estimator.seed(baselines_ohm);
+        estimator
+    }
+
+    /// set the number of updates required before is_settled() returns true
+    pub fn burn_in_samples(mut self, samples: u32) -> Self {
+        self.burn_in_samples = samples;
+        self
+    }
+
+    /// replace the baseline with known clean air resistances (in ohms) and mark it settled
+    pub fn seed(&mut self, baselines_ohm: [f32; HOT_PLATES]) {
+        self.baselines_ohm = baselines_ohm;
+        self.samples = self.samples.max(self.burn_in_samples);
+    }
+
+    /// feed the latest hot plate resistances (in ohms), returns the updated baselines
+    pub fn update(&mut self, resistances_ohm: &[f32; HOT_PLATES]) -> [f32; HOT_PLATES] {
+        if self.samples == 0 {
+            self.baselines_ohm = *resistances_ohm;
+        } else {
+            for (baseline, rs) in self.baselines_ohm.iter_mut().zip(resistances_ohm) {
+                let alpha = if *rs > *baseline {
+                    self.rise_alpha
+                } else {
+                    self.fall_alpha
+                };
+                *baseline += alpha * (*rs - *baseline);
+            }
+        }
+        self.samples = self.samples.saturating_add(1);
+        self.baselines_ohm
+    }
+
+    /// current baseline of each hot plate in ohms
+    pub fn baselines_ohm(&self) -> [f32; HOT_PLATES] {
+        self.baselines_ohm
+    }
+
+    /// number of updates seen (including restored state)
+    pub fn samples(&self) -> u32 {
+        self.samples
+    }
+
+    /// true once the burn-in is over or the baseline was seeded
+    pub fn is_settled(&self) -> bool {
+        self.samples >= self.burn_in_samples
+    }
+
+    /// serialize the estimator state, store the returned bytes in flash
+    pub fn save(&self) -> [u8; BASELINE_STATE_SIZE] {
+        let mut state = [0u8; BASELINE_STATE_SIZE];
+        state[0] = STATE_MAGIC;
+        state[1] = STATE_VERSION;
+        for (plate, baseline) in self.baselines_ohm.iter().enumerate() {
+            let start = 2 + plate * 4;
+            state[start..start + 4].copy_from_slice(&baseline.to_le_bytes());
+        }
+        state[18..22].copy_from_slice(&self.samples.to_le_bytes());
+        state[22] = checksum(&state[..22]);
+        state
+    }
+
+    /// restore an estimator from bytes written by save(), using the default filter weights
+    pub fn restore(state: &[u8]) -> Result<Self, BaselineStateError> {
+        let mut estimator = Self::default();
+        estimator.load(state)?;
+        Ok(estimator)
+    }
+
+    /// load saved state into this estimator, keeping its filter weights and burn-in setting
+    pub fn load(&mut self, state: &[u8]) -> Result<(), BaselineStateError> {
+        if state.len() < BASELINE_STATE_SIZE {
+            return Err(BaselineStateError::BadLength(state.len()));
+        }
+        if state[0] != STATE_MAGIC {
+            return Err(BaselineStateError::BadMagic(state[0]));
+        }
+        if state[1] != STATE_VERSION {
+            return Err(BaselineStateError::UnsupportedVersion(state[1]));
+        }
+        if state[22] != checksum(&state[..22]) {
+            return Err(BaselineStateError::BadChecksum);
+        }
+        for (plate, baseline) in self.baselines_ohm.iter_mut().enumerate() {
+            let start = 2 + plate * 4;
+            *baseline = f32::from_le_bytes([
+                state[start],
+                state[start + 1],
+                state[start + 2],
+                state[start + 3],
+            ]);
+        }
+        self.samples = u32::from_le_bytes([state[18], state[19], state[20], state[21]]);
+        Ok(())
+    }
+}
+
+// simple 8 bit Fletcher style checksum, enough to catch erased or torn flash writes
+fn checksum(bytes: &[u8]) -> u8 {
+    let mut sum1: u8 = 0;
+    let mut sum2: u8 = 0;
+    for byte in bytes {
+        sum1 = sum1.wrapping_add(*byte);
+        sum2 = sum2.wrapping_add(sum1);
+    }
+    sum1 ^ sum2
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn estimator() -> BaselineEstimator {
+        BaselineEstimator::from_factory([1000.0, 2000.0, 4000.0, 8000.0])
+    }
+
+    #[test]
+    fn save_restore_round_trip() {
+        let mut saved = estimator();
+        saved.update(&[1500.0, 1800.0, 4100.0, 7000.0]);
+        let restored = BaselineEstimator::restore(&saved.save()).unwrap();
+        assert_eq!(restored, saved);
+    }
+
+    #[test]
+    fn load_keeps_filter_weights_and_burn_in() {
+        let mut loaded = BaselineEstimator::new(0.5, 0.5).burn_in_samples(10_000);
+        loaded.load(&estimator().save()).unwrap();
+        assert_eq!(loaded.baselines_ohm(), [1000.0, 2000.0, 4000.0, 8000.0]);
+        assert_eq!(loaded.samples(), DEFAULT_BURN_IN_SAMPLES);
+        assert!(!loaded.is_settled());
+        loaded.update(&[2000.0; HOT_PLATES]);
+        assert_eq!(loaded.baselines_ohm()[0], 1500.0);
+    }
+
+    // the blob lives in flash across firmware updates, its layout must not change
+    #[test]
+    fn state_layout() {
+        #[rustfmt::skip]
+        let expected: [u8; BASELINE_STATE_SIZE] = [
+            0xb1, 0x01,
+            0x00, 0x00, 0x7a, 0x44,
+            0x00, 0x00, 0xfa, 0x44,
+            0x00, 0x00, 0x7a, 0x45,
+            0x00, 0x00, 0xfa, 0x45,
+            0xd0, 0x02, 0x00, 0x00,
+            0x51,
+        ];
+        assert_eq!(estimator().save(), expected);
+    }
+
+    #[test]
+    fn longer_state_is_accepted() {
+        let mut state = [0xffu8; 32];
+        state[..BASELINE_STATE_SIZE].copy_from_slice(&estimator().save());
+        assert_eq!(BaselineEstimator::restore(&state), Ok(estimator()));
+    }
+
+    #[test]
+    fn rejects_bad_length() {
+        let state = estimator().save();
+        assert_eq!(
+            BaselineEstimator::restore(&state[..BASELINE_STATE_SIZE - 1]),
+            Err(BaselineStateError::BadLength(BASELINE_STATE_SIZE - 1))
+        );
+    }
+
+    #[test]
+    fn rejects_bad_magic() {
+        // erased flash
+        let state = [0xffu8; BASELINE_STATE_SIZE];
+        assert_eq!(
+            BaselineEstimator::restore(&state),
+            Err(BaselineStateError::BadMagic(0xff))
+        );
+    }
+
+    #[test]
+    fn rejects_unsupported_version() {
+        let mut state = estimator().save();
+        state[1] = 2;
+        state[22] = checksum(&state[..22]);
+        assert_eq!(
+            BaselineEstimator::restore(&state),
+            Err(BaselineStateError::UnsupportedVersion(2))
+        );
+    }
+
+    #[test]
+    fn rejects_bad_checksum() {
+        let mut state = estimator().save();
+        state[5] ^= 0x01;
+        assert_eq!(
+            BaselineEstimator::restore(&state),
+            Err(BaselineStateError::BadChecksum)
+        );
+    }
+}
@@ -0,0 +1,472 @@
+// ens160 command line tool for Linux hosts, build with --features cli
+//
+//   ens160 --bus /dev/i2c-1 info --firmware
+//   ens160 monitor --interval 5 --format json
+
+use std::fmt::{Debug, Display};
+use std::io::{self, Write};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use clap::{Parser, Subcommand, ValueEnum};
+use embedded_hal::{delay::DelayNs, i2c::I2c};
+use linux_embedded_hal::{Delay, I2cdev};
+
+use ens160_aq::config::Ens160Config;
+use ens160_aq::constants::{DeviceAddress, Register};
+use ens160_aq::data::{InterruptPinConfig, Measurements, OperationMode, Status};
+use ens160_aq::Ens160;
+
+#[derive(Parser)]
+#[command(name = "ens160", version, about = "ScioSense ENS160 air quality sensor tool")]
+struct Cli {
+    /// I2C bus device
+    #[arg(short, long, default_value = "/dev/i2c-1")]
+    bus: String,
+    /// device address: primary (0x52), secondary (0x53) or auto to probe both
+    #[arg(short, long, value_enum, default_value_t = Address::Auto)]
+    address: Address,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Address {
+    Auto,
+    Primary,
+    Secondary,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// show part id, operating mode and status
+    Info {
+        /// also read the firmware version:  needs idle mode, so a running sensor restarts its warm-up
+        #[arg(long)]
+        firmware: bool,
+    },
+    /// read the measurements once (attaches to a running sensor, starts it otherwise)
+    Read {
+        #[arg(short, long, value_enum, default_value_t = Format::Text)]
+        format: Format,
+    },
+    /// read the measurements periodically
+    Monitor {
+        /// seconds between readings
+        #[arg(short, long, default_value_t = 1)]
+        interval: u64,
+        /// number of readings, runs until interrupted if not given
+        #[arg(short, long)]
+        count: Option<u64>,
+        #[arg(short, long, value_enum, default_value_t = Format::Csv)]
+        format: Format,
+    },
+    /// set the operating mode
+    SetMode {
+        #[arg(value_enum)]
+        mode: Mode,
+    },
+    /// set temperature (degrees C) and relative humidity (percent) compensation
+    SetComp { temp_c: f32, rh_percent: f32 },
+    /// configure the interrupt pin, disabled unless --enable is given
+    ConfigInt {
+        /// enable the interrupt pin
+        #[arg(long)]
+        enable: bool,
+        /// pin is high when active (default low)
+        #[arg(long)]
+        active_high: bool,
+        /// push-pull drive (default open drain)
+        #[arg(long)]
+        push_pull: bool,
+        /// interrupt on new data
+        #[arg(long)]
+        on_data: bool,
+        /// interrupt on new group data
+        #[arg(long)]
+        on_group_data: bool,
+    },
+    /// dump all registers
+    Dump,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum Format {
+    Text,
+    Csv,
+    Json,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Mode {
+    Sleep,
+    Idle,
+    Standard,
+    Reset,
+}
+
+impl From<Mode> for OperationMode {
+    fn from(mode: Mode) -> Self {
+        match mode {
+            Mode::Sleep => OperationMode::Sleep,
+            Mode::Idle => OperationMode::Idle,
+            Mode::Standard => OperationMode::Standard,
+            Mode::Reset => OperationMode::Reset,
+        }
+    }
+}
+
+fn main() {
+    let cli = Cli::parse();
+    let i2c = match I2cdev::new(&cli.bus) {
+        Ok(i2c) => i2c,
+        Err(err) => {
+            eprintln!("can not open {}: {}", cli.bus, err);
+            std::process::exit(1);
+        }
+    };
+    let mut ens160 = match cli.address {
+        Address::Primary => Ens160::new_with_address(i2c, Delay, DeviceAddress::Primary),
+        Address::Secondary => Ens160::new_with_address(i2c, Delay, DeviceAddress::Secondary),
+        Address::Auto => match Ens160::probe(i2c, Delay) {
+            Ok(ens160) => ens160,
+            Err((err, _, _)) => {
+                eprintln!("{} on {}", err, cli.bus);
+                std::process::exit(1);
+            }
+        },
+    };
+    let stdout = io::stdout();
+    if let Err(err) = run(&mut ens160, &cli.command, &mut stdout.lock()) {
+        eprintln!("error: {}", err);
+        std::process::exit(1);
+    }
+}
+
+/// run one command, generic over the bus so the tests can run it on the simulator.
+/// Errors are returned as their Display message.
+fn run<I2C, D, W>(ens160: &mut Ens160<I2C, D>, command: &Command, out: &mut W) -> Result<(), String>
+where
+    I2C: I2c,
+    I2C::Error: Debug,
+    D: DelayNs,
+    W: Write,
+{
+    match command {
+        Command::Info { firmware } => info(ens160, *firmware, out),
+        Command::Read { format } => {
+            ens160.attach(&Ens160Config::default()).map_err(message)?;
+            if *format == Format::Csv {
+                writeln!(out, "{}", CSV_HEADER).map_err(message)?;
+            }
+            read(ens160, *format, out)
+        }
+        Command::Monitor {
+            interval,
+            count,
+            format,
+        } => {
+            ens160.attach(&Ens160Config::default()).map_err(message)?;
+            if *format == Format::Csv {
+                writeln!(out, "{}", CSV_HEADER).map_err(message)?;
+            }
+            let mut readings = 0;
+            while count.is_none_or(|count| readings < count) {
+                if readings > 0 {
+                    thread::sleep(Duration::from_secs(*interval));
+                }
+                read(ens160, *format, out)?;
+                out.flush().map_err(message)?;
+                readings += 1;
+            }
+            Ok(())
+        }
+        Command::SetMode { mode } => {
+            let new_mode = ens160.set_operation_mode((*mode).into()).map_err(message)?;
+            writeln!(out, "operation mode is {:?}", new_mode).map_err(message)
+        }
+        Command::SetComp {
+            temp_c,
+            rh_percent,
+        } => {
+            ens160.set_temp_rh_comp(*temp_c, *rh_percent).map_err(message)?;
+            let (temp_c, rh) = ens160.get_temp_rh_comp().map_err(message)?;
+            writeln!(
+                out,
+                "compensation set to temperature = {} C, relative humidity = {} %",
+                temp_c, rh
+            )
+            .map_err(message)
+        }
+        Command::ConfigInt {
+            enable,
+            active_high,
+            push_pull,
+            on_data,
+            on_group_data,
+        } => {
+            let mut config = InterruptPinConfig::builder();
+            if *enable {
+                config = config.enable_interrupt();
+            }
+            if *active_high {
+                config = config.active_high();
+            }
+            if *push_pull {
+                config = config.push_pull();
+            }
+            if *on_data {
+                config = config.on_new_data();
+            }
+            if *on_group_data {
+                config = config.on_new_group_data();
+            }
+            let value = config.build();
+            let read_back = ens160.config_interrupt_pin(value).map_err(message)?;
+            writeln!(out, "config written {:#04x}, read back {:#04x}", value, read_back)
+                .map_err(message)
+        }
+        Command::Dump => {
+            for register in Register::ALL {
+                let mut buffer = [0u8; 8];
+                let buffer = &mut buffer[..register.size()];
+                ens160.read_register(register, buffer).map_err(message)?;
+                write!(
+                    out,
+                    "{:#04x} {:<12} {:<9}",
+                    register.address(),
+                    format!("{:?}", register),
+                    format!("{:?}", register.access())
+                )
+                .map_err(message)?;
+                for byte in buffer.iter() {
+                    write!(out, " {:02x}", byte).map_err(message)?;
+                }
+                writeln!(out).map_err(message)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+fn info<I2C, D, W>(ens160: &mut Ens160<I2C, D>, firmware: bool, out: &mut W) -> Result<(), String>
+where
+    I2C: I2c,
+    I2C::Error: Debug,
+    D: DelayNs,
+    W: Write,
+{
+    let part_id = ens160.get_part_id().map_err(message)?;
+    writeln!(out, "part id:   {:#06x}", part_id).map_err(message)?;
+    let mut opmode = [0u8; 1];
+    ens160.read_register(Register::OpMode, &mut opmode).map_err(message)?;
+    writeln!(out, "opmode:    {:#04x}", opmode[0]).map_err(message)?;
+    if firmware {
+        ens160.set_operation_mode(OperationMode::Idle).map_err(message)?;
+        let version = ens160.get_firmware_version().map_err(message)?;
+        writeln!(out, "firmware:  {}", version).map_err(message)?;
+        if let Ok(mode) = OperationMode::try_from(opmode[0]) {
+            if mode != OperationMode::Idle {
+                ens160.set_operation_mode(mode).map_err(message)?;
+            }
+        }
+    }
+    let status = ens160.get_status().map_err(message)?;
+    writeln!(out, "status:    {:#04x}", status.0).map_err(message)?;
+    writeln!(out, "validity:  {:?}", status.validity_flag()).map_err(message)?;
+    writeln!(out, "error:     {}", status.error()).map_err(message)?;
+    Ok(())
+}
+
+const CSV_HEADER: &str =
+    "timestamp_ms,validity,eco2_ppm,tvoc_ppb,aqi,etoh_ppb,raw_resistance_ohm";
+
+fn read<I2C, D, W>(ens160: &mut Ens160<I2C, D>, format: Format, out: &mut W) -> Result<(), String>
+where
+    I2C: I2c,
+    I2C::Error: Debug,
+    D: DelayNs,
+    W: Write,
+{
+    let status: Status = ens160.get_status().map_err(message)?;
+    let measurements: Measurements = ens160.get_measurements().map_err(message)?;
+    let timestamp_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_millis())
+        .unwrap_or_default();
+    let validity = status.validity_flag();
+    let result = match format {
+        Format::Text => writeln!(
+            out,
+            "{:?}: eCO2 {} ppm, TVOC {} ppb, AQI {:?}, ethanol {} ppb, resistance {} ohm",
+            validity,
+            measurements.co2eq_ppm.get_value(),
+            measurements.tvoc_ppb,
+            measurements.air_quality_index,
+            measurements.etoh,
+            measurements.raw_resistance
+        ),
+        Format::Csv => writeln!(
+            out,
+            "{},{:?},{},{},{},{},{}",
+            timestamp_ms,
+            validity,
+            measurements.co2eq_ppm.get_value(),
+            measurements.tvoc_ppb,
+            measurements.air_quality_index as u8,
+            measurements.etoh,
+            measurements.raw_resistance
+        ),
+        Format::Json => writeln!(
+            out,
+            "{{\"timestamp_ms\":{},\"validity\":\"{:?}\",\"eco2_ppm\":{},\"tvoc_ppb\":{},\"aqi\":{},\"etoh_ppb\":{},\"raw_resistance_ohm\":{}}}",
+            timestamp_ms,
+            validity,
+            measurements.co2eq_ppm.get_value(),
+            measurements.tvoc_ppb,
+            measurements.air_quality_index as u8,
+            measurements.etoh,
+            measurements.raw_resistance
+        ),
+    };
+    result.map_err(message)
+}
+
+fn message<T: Display>(value: T) -> String {
+    value.to_string()
+}
+
+// the commands run against the behavioural simulator, build with --features cli,sim
+#[cfg(all(test, feature = "sim"))]
+mod tests {
+    use core::cell::RefCell;
+
+    use super::*;
+    use ens160_aq::sim::{Ens160Sim, SimConfig, SimDelay, SimI2c};
+
+    // parse the command line arguments and run them on a fresh simulated sensor
+    fn run_args(args: &[&str]) -> Result<String, String> {
+        let sim = RefCell::new(Ens160Sim::new(SimConfig::default(), &[]));
+        run_args_on(&sim, args)
+    }
+
+    fn run_args_on(sim: &RefCell<Ens160Sim>, args: &[&str]) -> Result<String, String> {
+        let cli = Cli::try_parse_from(["ens160"].iter().chain(args)).map_err(message)?;
+        let mut ens160 = Ens160::new(SimI2c::new(sim), SimDelay::new(sim));
+        let mut out = Vec::new();
+        run(&mut ens160, &cli.command, &mut out)?;
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn info() {
+        let out = run_args(&["info", "--firmware"]).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines[0], "part id:   0x0160");
+        assert_eq!(lines[1], "opmode:    0x00");
+        assert_eq!(lines[2], "firmware:  5.4.6");
+        assert!(lines[3].starts_with("status:    0x"));
+        assert_eq!(lines[4], "validity:  InvalidOutput");
+        assert_eq!(lines[5], "error:     false");
+    }
+
+    #[test]
+    fn read_text() {
+        let out = run_args(&["read"]).unwrap();
+        assert!(out.starts_with("WarmupPhase: eCO2 "), "{}", out);
+        assert!(out.contains(" ppm, TVOC "));
+        assert!(out.trim_end().ends_with(" ohm"));
+    }
+
+    #[test]
+    fn read_csv() {
+        let out = run_args(&["read", "--format", "csv"]).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0], CSV_HEADER);
+        let fields: Vec<&str> = lines[1].split(',').collect();
+        assert_eq!(fields.len(), CSV_HEADER.split(',').count());
+        assert_eq!(fields[1], "WarmupPhase");
+        assert!(fields[0].parse::<u128>().is_ok());
+        for field in &fields[2..6] {
+            assert!(field.parse::<u16>().is_ok(), "{}", lines[1]);
+        }
+        assert!(fields[6].parse::<f32>().unwrap() > 0.0);
+    }
+
+    #[test]
+    fn read_json() {
+        let out = run_args(&["read", "--format", "json"]).unwrap();
+        assert_eq!(out.lines().count(), 1);
+        assert!(out.starts_with("{\"timestamp_ms\":"));
+        assert!(out.contains(",\"validity\":\"WarmupPhase\",\"eco2_ppm\":"));
+        for key in ["tvoc_ppb", "aqi", "etoh_ppb", "raw_resistance_ohm"] {
+            assert!(
+                out.contains(&format!(",\"{}\":", key)),
+                "{} in {}",
+                key,
+                out
+            );
+        }
+        assert!(out.trim_end().ends_with('}'));
+    }
+
+    #[test]
+    fn read_attaches_to_running_sensor() {
+        let sim = RefCell::new(Ens160Sim::new(SimConfig::default(), &[]));
+        run_args_on(&sim, &["set-mode", "standard"]).unwrap();
+        sim.borrow_mut().advance(10 * 60 * 1000);
+        let out = run_args_on(&sim, &["read"]).unwrap();
+        assert!(out.starts_with("NormalOperation: "), "{}", out);
+    }
+
+    #[test]
+    fn dump() {
+        let out = run_args(&["dump"]).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines.len(), Register::ALL.len());
+        assert!(lines[0].starts_with("0x00 PartId"), "{}", lines[0]);
+        assert!(lines[0].ends_with(" 60 01"), "{}", lines[0]);
+        for (line, register) in lines.iter().zip(Register::ALL) {
+            let bytes = line.split_whitespace().count() - 3;
+            assert_eq!(bytes, register.size(), "{}", line);
+        }
+    }
+
+    #[test]
+    fn set_comp() {
+        let out = run_args(&["set-comp", "21.5", "41"]).unwrap();
+        let rest = out
+            .strip_prefix("compensation set to temperature = ")
+            .unwrap();
+        let (temp_c, rest) = rest.split_once(" C, ").unwrap();
+        // TEMP_IN resolution is 1/64 K
+        assert!(
+            (temp_c.parse::<f32>().unwrap() - 21.5).abs() <= 1.0 / 64.0,
+            "{}",
+            out
+        );
+        assert_eq!(rest, "relative humidity = 41 %\n");
+    }
+
+    #[test]
+    fn set_mode() {
+        let out = run_args(&["set-mode", "idle"]).unwrap();
+        assert_eq!(out, "operation mode is Idle\n");
+    }
+
+    #[test]
+    fn sensor_missing() {
+        let config = SimConfig {
+            address: DeviceAddress::Secondary,
+            ..Default::default()
+        };
+        let sim = RefCell::new(Ens160Sim::new(config, &[]));
+        let err = run_args_on(&sim, &["info"]).unwrap_err();
+        assert!(
+            err.starts_with("I2C read of register 0x00 (PartId) failed"),
+            "{}",
+            err
+        );
+    }
+}
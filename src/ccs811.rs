@@ -0,0 +1,283 @@
+// CCS811 migration facade:  the shape of the common CCS811 drivers (embedded-ccs811) on top
+// of Ens160, so legacy application code can switch sensors with minimal changes.
+//
+//   let mut sensor = Ccs811Compat::new(Ens160::new(i2c, delay));
+//   sensor.start_application()?;
+//   sensor.set_mode(MeasurementMode::ConstantPower1s)?;
+//   let data = nb::block!(sensor.data())?;
+
+use embedded_hal::{delay::DelayNs, i2c::I2c};
+use libm::{exp2f, log2f, roundf};
+
+use crate::baseline::BaselineEstimator;
+use crate::config::Ens160Config;
+use crate::data::OperationMode;
+use crate::error::Error;
+use crate::Ens160;
+
+/// CCS811 measurement modes.  The ENS160 measures once per second in standard mode
+/// whatever the mode, every mode except Idle starts it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MeasurementMode {
+    /// no measurements
+    Idle,
+    /// measurement every second
+    ConstantPower1s,
+    /// measurement every 10 seconds
+    PulseHeating10s,
+    /// measurement every 60 seconds
+    LowPowerPulseHeating60s,
+    /// measurement every 250 ms
+    ConstantPower250ms,
+}
+
+/// algorithm result as returned by CCS811 drivers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AlgorithmResult {
+    /// equivalent CO2 in ppm
+    pub eco2: u16,
+    /// equivalent TVOC in ppb
+    pub etvoc: u16,
+    /// CCS811 sensor current in uA, always 0 (the ENS160 has no equivalent)
+    pub raw_current: u8,
+    /// CCS811 sensor voltage ADC reading, always 0 (the ENS160 has no equivalent)
+    pub raw_voltage: u16,
+}
+
+/// Ens160 with a CCS811 style API.  The ENS160 corrects its baseline itself, so
+/// baseline() and set_baseline() save and restore the host-side BaselineEstimator (fed by
+/// every data() read) instead, encoded in the two bytes a CCS811 baseline takes.
+pub struct Ccs811Compat<I2C, D> {
+    sensor: Ens160<I2C, D>,
+    estimator: BaselineEstimator,
+}
+
+impl<I2C, D, E> Ccs811Compat<I2C, D>
+where
+    I2C: I2c<Error = E>,
+    D: DelayNs,
+{
+    /// CCS811 style API for sensor
+    pub fn new(sensor: Ens160<I2C, D>) -> Self {
+        Self::with_estimator(sensor, BaselineEstimator::default())
+    }
+
+    /// CCS811 style API for sensor with a configured (or restored) baseline estimator
+    pub fn with_estimator(sensor: Ens160<I2C, D>, estimator: BaselineEstimator) -> Self {
+        Ccs811Compat { sensor, estimator }
+    }
+
+    /// the wrapped sensor, for ENS160 features the CCS811 does not have
+    pub fn sensor_mut(&mut self) -> &mut Ens160<I2C, D> {
+        &mut self.sensor
+    }
+
+    /// the host-side baseline estimator
+    pub fn estimator(&self) -> &BaselineEstimator {
+        &self.estimator
+    }
+
+    /// give back the wrapped sensor
+    pub fn into_inner(self) -> Ens160<I2C, D> {
+        self.sensor
+    }
+
+    /// CCS811 boot to application mode:  initializes the ENS160, leaving it in idle mode
+    /// until set_mode()
+    pub fn start_application(&mut self) -> Result<(), Error<E>> {
+        let config = Ens160Config::builder()
+            .operation_mode(OperationMode::Idle)
+            .build();
+        self.sensor.initialize_with(&config)?;
+        Ok(())
+    }
+
+    /// set the measurement mode, see MeasurementMode
+    pub fn set_mode(&mut self, mode: MeasurementMode) -> Result<(), Error<E>> {
+        let target = match mode {
+            MeasurementMode::Idle => OperationMode::Idle,
+            _ => OperationMode::Standard,
+        };
+        let new_mode = self.sensor.set_operation_mode(target)?;
+        if new_mode != target {
+            return Err(Error::OpModeNotCorrect(new_mode as u8));
+        }
+        Ok(())
+    }
+
+    /// true if a new measurement is available
+    pub fn has_data_ready(&mut self) -> Result<bool, Error<E>> {
+        Ok(self.sensor.get_status()?.new_data_ready())
+    }
+
+    /// the new measurement, WouldBlock until one is available
+    pub fn data(&mut self) -> nb::Result<AlgorithmResult, Error<E>> {
+        if !self.has_data_ready()? {
+            return Err(nb::Error::WouldBlock);
+        }
+        let eco2 = self.sensor.get_eco2()?.get_value();
+        let etvoc = self.sensor.get_tvoc()?;
+        let resistances = self.sensor.get_raw_resistances()?;
+        self.estimator.update(&resistances);
+        Ok(AlgorithmResult {
+            eco2,
+            etvoc,
+            raw_current: 0,
+            raw_voltage: 0,
+        })
+    }
+
+    /// ambient relative humidity (percent) and temperature (degrees C) for compensation,
+    /// in the CCS811 drivers' argument order.  Both keep their fractions, RH_IN is written
+    /// in 1/512 percent like set_temp_rh_comp().
+    pub fn set_environment(
+        &mut self,
+        humidity_percentage: f32,
+        temperature_celsius: f32,
+    ) -> Result<(), Error<E>> {
+        let rh_percent = humidity_percentage.clamp(0.0, 100.0);
+        self.sensor.set_temp_rh_comp(temperature_celsius, rh_percent)
+    }
+
+    /// baseline to store and restore with set_baseline():  the geometric mean of the hot
+    /// plate baselines in the ENS160 raw resistance encoding (log2(ohm) * 2048, big endian),
+    /// [0, 0] before the first data() read
+    pub fn baseline(&mut self) -> Result<[u8; 2], Error<E>> {
+        if self.estimator.samples() == 0 {
+            return Ok([0; 2]);
+        }
+        let value = roundf(mean_log2(&self.estimator.baselines_ohm()) * 2048.0);
+        Ok((value.clamp(0.0, u16::MAX as f32) as u16).to_be_bytes())
+    }
+
+    /// restore a baseline from baseline().  The hot plates keep their current ratios
+    /// to each other, only their mean is set, so the estimator is treated as settled.
+    pub fn set_baseline(&mut self, baseline: [u8; 2]) -> Result<(), Error<E>> {
+        let target = u16::from_be_bytes(baseline) as f32 / 2048.0;
+        let mut baselines_ohm = self.estimator.baselines_ohm();
+        if self.estimator.samples() == 0 {
+            baselines_ohm = baselines_ohm.map(|_| exp2f(target));
+        } else {
+            let shift = target - mean_log2(&baselines_ohm);
+            baselines_ohm = baselines_ohm.map(|ohm| exp2f(log2f(ohm.max(1.0)) + shift));
+        }
+        self.estimator.seed(baselines_ohm);
+        Ok(())
+    }
+}
+
+// mean of the log2 of the resistances (log2 of their geometric mean)
+fn mean_log2(resistances_ohm: &[f32]) -> f32 {
+    let sum: f32 = resistances_ohm.iter().map(|ohm| log2f(ohm.max(1.0))).sum();
+    sum / resistances_ohm.len() as f32
+}
+
+#[cfg(all(test, feature = "sim"))]
+mod tests {
+    use core::cell::RefCell;
+
+    use super::*;
+    use crate::constants::ENS160_RH_IN;
+    use crate::data::HOT_PLATES;
+    use crate::sim::{Ens160Sim, SimConfig, SimDelay, SimI2c, CYCLE_MS};
+
+    type SimCcs811<'s, 'e> = Ccs811Compat<SimI2c<'s, 'e>, SimDelay<'s, 'e>>;
+
+    // started sensor measuring once per second
+    fn measuring<'s, 'e>(sim: &'s RefCell<Ens160Sim<'e>>) -> SimCcs811<'s, 'e> {
+        let mut sensor = Ccs811Compat::new(Ens160::new(SimI2c::new(sim), SimDelay::new(sim)));
+        sensor.start_application().unwrap();
+        sensor.set_mode(MeasurementMode::ConstantPower1s).unwrap();
+        sensor
+    }
+
+    // baseline() bytes as the raw resistance encoding
+    fn code(baseline: [u8; 2]) -> f32 {
+        u16::from_be_bytes(baseline) as f32
+    }
+
+    #[test]
+    fn set_environment_keeps_fractions() {
+        let sim = RefCell::new(Ens160Sim::new(SimConfig::default(), &[]));
+        let mut sensor = Ccs811Compat::new(Ens160::new(SimI2c::new(&sim), SimDelay::new(&sim)));
+        sensor.start_application().unwrap();
+        for (humidity, rh_in) in [(50.0, 25_600), (41.25, 21_120), (0.5, 256), (120.0, 51_200)] {
+            sensor.set_environment(humidity, 21.5).unwrap();
+            let mut raw = [0u8; 2];
+            sensor
+                .sensor_mut()
+                .read_address(ENS160_RH_IN, &mut raw)
+                .unwrap();
+            assert_eq!(u16::from_le_bytes(raw), rh_in, "{}", humidity);
+        }
+        let (temp_c, rh_percent) = sensor.sensor_mut().get_temp_rh_comp().unwrap();
+        assert!((temp_c - 21.5).abs() < 1.0 / 64.0);
+        assert_eq!(rh_percent, 100.0);
+    }
+
+    #[test]
+    fn baseline_is_zero_before_data() {
+        let sim = RefCell::new(Ens160Sim::new(SimConfig::default(), &[]));
+        let mut sensor = measuring(&sim);
+        assert_eq!(sensor.baseline().unwrap(), [0, 0]);
+        sim.borrow_mut().advance(CYCLE_MS);
+        // a measurement is ready but was not read
+        assert_eq!(sensor.baseline().unwrap(), [0, 0]);
+    }
+
+    #[test]
+    fn data_would_block_without_new_data() {
+        let sim = RefCell::new(Ens160Sim::new(SimConfig::default(), &[]));
+        let mut sensor = measuring(&sim);
+        assert!(matches!(sensor.data(), Err(nb::Error::WouldBlock)));
+        sim.borrow_mut().advance(CYCLE_MS);
+        assert!(sensor.has_data_ready().unwrap());
+        let data = sensor.data().unwrap();
+        assert_eq!((data.raw_current, data.raw_voltage), (0, 0));
+        assert_eq!(sensor.estimator().samples(), 1);
+        // the measurement was consumed, the next one is a cycle away
+        assert!(matches!(sensor.data(), Err(nb::Error::WouldBlock)));
+        assert_eq!(sensor.estimator().samples(), 1);
+    }
+
+    #[test]
+    fn baseline_round_trip() {
+        let sim = RefCell::new(Ens160Sim::new(SimConfig::default(), &[]));
+        let mut sensor = measuring(&sim);
+        for _ in 0..10 {
+            sim.borrow_mut().advance(CYCLE_MS);
+            sensor.data().unwrap();
+        }
+        let baseline = sensor.baseline().unwrap();
+        let mean = mean_log2(&sensor.estimator().baselines_ohm()) * 2048.0;
+        assert!(
+            (code(baseline) - mean).abs() <= 0.5,
+            "{:?} {}",
+            baseline,
+            mean
+        );
+
+        let other = RefCell::new(Ens160Sim::new(SimConfig::default(), &[]));
+        let mut restored =
+            Ccs811Compat::new(Ens160::new(SimI2c::new(&other), SimDelay::new(&other)));
+        assert!(!restored.estimator().is_settled());
+        restored.set_baseline(baseline).unwrap();
+        assert!(restored.estimator().is_settled());
+        let restored_mean = mean_log2(&restored.estimator().baselines_ohm()) * 2048.0;
+        assert!(
+            (restored_mean - code(baseline)).abs() <= 1.0,
+            "{}",
+            restored_mean
+        );
+        assert_eq!(restored.baseline().unwrap(), baseline);
+
+        // restoring into a running estimator moves the mean, not the plate ratios
+        let ratio = |ohm: [f32; HOT_PLATES]| ohm[0] / ohm[3];
+        let before = ratio(sensor.estimator().baselines_ohm());
+        let shifted = (u16::from_be_bytes(baseline) + 2048).to_be_bytes();
+        sensor.set_baseline(shifted).unwrap();
+        let mean = mean_log2(&sensor.estimator().baselines_ohm()) * 2048.0;
+        assert!((mean - code(shifted)).abs() <= 1.0, "{}", mean);
+        assert!((ratio(sensor.estimator().baselines_ohm()) / before - 1.0).abs() < 1.0e-3);
+    }
+}
@@ -0,0 +1,179 @@
+// COMMAND register interface:  each command is a type with its code, optional GPR_WRITE
+// parameters and a decoder for its GPR_READ response, run by Ens160::execute()
+
+use crate::data::ENS160Command;
+use crate::firmware::FirmwareVersion;
+
+/// a command for the COMMAND register, see Ens160::execute()
+pub trait Command {
+    /// decoded response of the command
+    type Response;
+
+    /// COMMAND register value
+    fn code(&self) -> u8;
+
+    /// written to GPR_WRITE0..7 before the command, None if the command takes no parameters
+    fn parameters(&self) -> Option<[u8; 8]> {
+        None
+    }
+
+    /// true if the command leaves a response in GPR_READ0..7, execute() then waits for
+    /// new group data and reads it
+    fn has_response(&self) -> bool {
+        true
+    }
+
+    /// decode the GPR_READ0..7 registers (all zero for commands without a response)
+    fn decode(&self, gpr_read: [u8; 8]) -> Self::Response;
+}
+
+/// no operation
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Nop;
+
+impl Command for Nop {
+    type Response = ();
+
+    fn code(&self) -> u8 {
+        ENS160Command::Nop as u8
+    }
+
+    fn has_response(&self) -> bool {
+        false
+    }
+
+    fn decode(&self, _gpr_read: [u8; 8]) {}
+}
+
+/// read the firmware (app) version from GPR_READ4..6
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GetAppVersion;
+
+impl Command for GetAppVersion {
+    type Response = FirmwareVersion;
+
+    fn code(&self) -> u8 {
+        ENS160Command::GetAppVersion as u8
+    }
+
+    fn decode(&self, gpr_read: [u8; 8]) -> FirmwareVersion {
+        FirmwareVersion::new(gpr_read[4], gpr_read[5], gpr_read[6])
+    }
+}
+
+/// clear the GPR_READ registers
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ClearGpr;
+
+impl Command for ClearGpr {
+    type Response = ();
+
+    fn code(&self) -> u8 {
+        ENS160Command::ClearGPR as u8
+    }
+
+    fn has_response(&self) -> bool {
+        false
+    }
+
+    fn decode(&self, _gpr_read: [u8; 8]) {}
+}
+
+/// any command code, e.g. one not documented in the datasheet, with the raw GPR_READ bytes
+/// as response
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RawCommand {
+    /// COMMAND register value
+    pub code: u8,
+    /// GPR_WRITE0..7 parameters, if any
+    pub parameters: Option<[u8; 8]>,
+    /// the command leaves a response in GPR_READ0..7
+    pub has_response: bool,
+}
+
+impl RawCommand {
+    /// command without parameters that leaves a response
+    pub fn new(code: u8) -> Self {
+        RawCommand {
+            code,
+            parameters: None,
+            has_response: true,
+        }
+    }
+
+    /// write these parameters to GPR_WRITE0..7 before the command
+    pub fn parameters(mut self, parameters: [u8; 8]) -> Self {
+        self.parameters = Some(parameters);
+        self
+    }
+
+    /// the command leaves no response, do not wait for new group data
+    pub fn no_response(mut self) -> Self {
+        self.has_response = false;
+        self
+    }
+}
+
+impl Command for RawCommand {
+    type Response = [u8; 8];
+
+    fn code(&self) -> u8 {
+        self.code
+    }
+
+    fn parameters(&self) -> Option<[u8; 8]> {
+        self.parameters
+    }
+
+    fn has_response(&self) -> bool {
+        self.has_response
+    }
+
+    fn decode(&self, gpr_read: [u8; 8]) -> [u8; 8] {
+        gpr_read
+    }
+}
+
+#[cfg(all(test, feature = "sim", not(feature = "async")))]
+mod tests {
+    use core::cell::RefCell;
+
+    use crate::config::Ens160Config;
+    use crate::data::OperationMode;
+    use crate::error::Error;
+    use crate::firmware::FirmwareVersion;
+    use crate::sim::{Ens160Sim, SimConfig, SimDelay, SimI2c};
+    use crate::Ens160;
+
+    #[test]
+    fn commands_need_idle_mode() {
+        let sim = RefCell::new(Ens160Sim::new(SimConfig::default(), &[]));
+        let mut ens160 = Ens160::new(SimI2c::new(&sim), SimDelay::new(&sim));
+        assert!(ens160.initialize().unwrap());
+        assert!(matches!(
+            ens160.get_firmware_version(),
+            Err(Error::NotIdle(0x02))
+        ));
+        assert!(matches!(
+            ens160.clear_command(),
+            Err(Error::NotIdle(0x02))
+        ));
+    }
+
+    #[test]
+    fn clear_command_clears_group_data() {
+        let sim = RefCell::new(Ens160Sim::new(SimConfig::default(), &[]));
+        let mut ens160 = Ens160::new(SimI2c::new(&sim), SimDelay::new(&sim));
+        let config = Ens160Config::builder()
+            .operation_mode(OperationMode::Idle)
+            .build();
+        ens160.initialize_with(&config).unwrap();
+        assert_eq!(
+            ens160.get_firmware_version().unwrap(),
+            FirmwareVersion::TESTED
+        );
+        assert_ne!(ens160.get_group_data().unwrap(), [0; 8]);
+        ens160.clear_command().unwrap();
+        assert_eq!(ens160.get_group_data().unwrap(), [0; 8]);
+    }
+}
@@ -0,0 +1,187 @@
+// declarative initialization configuration, see Ens160::initialize_with()
+
+use crate::data::{OperationMode, ValidityFlag};
+use crate::firmware::FirmwareVersion;
+
+/// delays (in ms) used for mode transitions and commands.  Start from a profile:
+/// legacy() (the default), conservative() for slow-starting boards or fast() for tight
+/// boot budgets, and adjust single fields if needed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timing {
+    /// after writing OPMODE, before reading it back
+    pub mode_change_ms: u32,
+    /// after entering idle mode, before the first command
+    pub idle_ms: u32,
+    /// after clearing the GPR registers
+    pub clear_gpr_ms: u32,
+    /// after reading the firmware version
+    pub firmware_ms: u32,
+    /// after entering the target operating mode
+    pub standard_ms: u32,
+    /// after a command, before its completion is polled on the status register
+    pub command_ms: u32,
+    /// give up polling for command completion after this long and read the result anyway
+    pub command_timeout_ms: u32,
+    /// between status register reads while polling
+    pub poll_interval_ms: u32,
+}
+
+impl Timing {
+    /// the delays earlier driver versions had hard-coded (50 ms for mode changes, idle and
+    /// GPR clearing, 10 ms after the firmware version, 150 ms into the target mode), the
+    /// default.  The datasheet gives no command delays:  command_ms, command_timeout_ms and
+    /// poll_interval_ms are this driver's choice
+    pub const fn legacy() -> Self {
+        Timing {
+            mode_change_ms: 50,
+            idle_ms: 50,
+            clear_gpr_ms: 50,
+            firmware_ms: 10,
+            standard_ms: 150,
+            command_ms: 10,
+            command_timeout_ms: 100,
+            poll_interval_ms: 5,
+        }
+    }
+
+    /// longer delays for boards with slow power-up or long I2C lines
+    pub const fn conservative() -> Self {
+        Timing {
+            mode_change_ms: 100,
+            idle_ms: 100,
+            clear_gpr_ms: 100,
+            firmware_ms: 50,
+            standard_ms: 500,
+            command_ms: 50,
+            command_timeout_ms: 500,
+            poll_interval_ms: 10,
+        }
+    }
+
+    /// short delays relying on command completion polling, for tight boot budgets
+    pub const fn fast() -> Self {
+        Timing {
+            mode_change_ms: 10,
+            idle_ms: 10,
+            clear_gpr_ms: 10,
+            firmware_ms: 0,
+            standard_ms: 20,
+            command_ms: 1,
+            command_timeout_ms: 50,
+            poll_interval_ms: 1,
+        }
+    }
+}
+
+impl Default for Timing {
+    fn default() -> Self {
+        Timing::legacy()
+    }
+}
+
+/// ENS160 initialization configuration, applied by Ens160::initialize_with().
+/// Use Ens160Config::builder() and its methods, the defaults match initialize().
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ens160Config {
+    /// operating mode to end up in, usually Standard
+    pub operation_mode: OperationMode,
+    /// interrupt pin configuration (see InterruptPinConfig), None leaves it unchanged
+    pub interrupt_config: Option<u8>,
+    /// initial temperature (degrees C) and relative humidity (percent) compensation
+    pub compensation: Option<(f32, f32)>,
+    /// oldest acceptable firmware version
+    pub min_firmware: Option<FirmwareVersion>,
+    /// delays of the initialization sequence
+    pub timing: Timing,
+}
+
+impl Default for Ens160Config {
+    fn default() -> Self {
+        Ens160Config {
+            operation_mode: OperationMode::Standard,
+            interrupt_config: None,
+            compensation: None,
+            min_firmware: None,
+            timing: Timing::default(),
+        }
+    }
+}
+
+impl Ens160Config {
+    /// builder starts with the default configuration
+    pub fn builder() -> Ens160Config {
+        Ens160Config::default()
+    }
+    /// operating mode to end up in after initialization
+    pub fn operation_mode(mut self, mode: OperationMode) -> Self {
+        self.operation_mode = mode;
+        self
+    }
+    /// interrupt pin configuration, see InterruptPinConfig
+    pub fn interrupt_config(mut self, config: u8) -> Self {
+        self.interrupt_config = Some(config);
+        self
+    }
+    /// initial temperature (degrees C) and relative humidity (percent) compensation
+    pub fn compensation(mut self, temp_c: f32, rh_percent: f32) -> Self {
+        self.compensation = Some((temp_c, rh_percent));
+        self
+    }
+    /// fail initialization if the firmware is older than this version
+    pub fn min_firmware(mut self, version: FirmwareVersion) -> Self {
+        self.min_firmware = Some(version);
+        self
+    }
+    /// delays of the initialization sequence
+    pub fn timing(mut self, timing: Timing) -> Self {
+        self.timing = timing;
+        self
+    }
+    /// build: just returns the configuration
+    pub fn build(self) -> Self {
+        self
+    }
+}
+
+/// ENS160 variant, identified by its part id
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Variant {
+    /// ENS160, part id 0x0160
+    Ens160,
+    /// ENS161 (drop-in successor), part id 0x0161
+    Ens161,
+}
+
+impl Variant {
+    /// variant for a part id, None if it is not a known ENS16x part id
+    pub fn from_part_id(part_id: u16) -> Option<Self> {
+        match part_id {
+            0x0160 => Some(Variant::Ens160),
+            0x0161 => Some(Variant::Ens161),
+            _ => None,
+        }
+    }
+}
+
+/// device information returned by Ens160::initialize_with() and Ens160::attach()
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeviceInfo {
+    /// part id register value
+    pub part_id: u16,
+    /// variant identified by the part id
+    pub variant: Variant,
+    /// firmware version.  None after attaching to a running sensor,
+    /// the version can only be read in idle mode.
+    pub firmware: Option<FirmwareVersion>,
+}
+
+/// result of Ens160::attach()
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Attached {
+    /// device part id, variant and firmware (if read)
+    pub device: DeviceInfo,
+    /// true if the sensor had to be fully initialized, restarting its warm-up
+    pub reinitialized: bool,
+    /// data validity flag read from the status register when attaching
+    pub validity: ValidityFlag,
+}
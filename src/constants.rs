@@ -1,4 +1,4 @@
-// ENS160 registers
+// ENS160 registers, see the Register enum for sizes and access rights
 pub const ENS160_PART_ID: u8 = 0x00;
 pub const ENS160_OPMODE: u8 = 0x10;
 pub const ENS160_CONFIG: u8 = 0x11;
@@ -9,25 +9,154 @@ pub const ENS160_DEVICE_STATUS: u8 = 0x20;
 pub const ENS160_DATA_AQI: u8 = 0x21;
 pub const ENS160_DATA_TVOC: u8 = 0x22;
 pub const ENS160_DATA_ECO2: u8 = 0x24;
-pub const ENS160_DATA_ETOH: u8 = 0x22; // is this correct?
+/// ethanol concentration shares its address with TVOC (datasheet register map)
+pub const ENS160_DATA_ETOH: u8 = 0x22;
 pub const ENS160_DATA_T: u8 = 0x30;
 pub const ENS160_DATA_RH: u8 = 0x32;
 pub const ENS160_DATA_MISR: u8 = 0x38;
 pub const ENS160_GPR_WRITE: u8 = 0x40;
 pub const ENS160_GPR_READ: u8 = 0x48;
+/// GPR_READ6:  last hot plate raw resistance, part of the GPR_READ block
 pub const ENS160_GRP_READ6: u8 = 0x4e;
 
+/// register access rights
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    /// read only
+    ReadOnly,
+    /// read and write
+    ReadWrite,
+}
+
+/// ENS160 register map (datasheet section 10)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Register {
+    /// device part id, 0x0160 (2 bytes)
+    PartId,
+    /// operating mode (1 byte)
+    OpMode,
+    /// interrupt pin configuration (1 byte)
+    Config,
+    /// additional system commands (1 byte)
+    Command,
+    /// host ambient temperature for compensation, Kelvin * 64 (2 bytes)
+    TempIn,
+    /// host relative humidity for compensation (2 bytes)
+    RhIn,
+    /// operating mode and data validity status (1 byte)
+    DeviceStatus,
+    /// air quality index according to UBA (1 byte)
+    DataAqi,
+    /// TVOC concentration in ppb (2 bytes)
+    DataTvoc,
+    /// equivalent CO2 concentration in ppm (2 bytes)
+    DataEco2,
+    /// ethanol concentration in ppb, same address as DataTvoc (2 bytes)
+    DataEtoh,
+    /// temperature used in the calculations, Kelvin * 64 (2 bytes)
+    DataT,
+    /// relative humidity used in the calculations (2 bytes)
+    DataRh,
+    /// data integrity field, optional (1 byte)
+    DataMisr,
+    /// general purpose write registers (8 bytes)
+    GprWrite,
+    /// general purpose read registers (8 bytes)
+    GprRead,
+}
+
+impl Register {
+    /// all registers, in address order
+    pub const ALL: [Register; 16] = [
+        Register::PartId,
+        Register::OpMode,
+        Register::Config,
+        Register::Command,
+        Register::TempIn,
+        Register::RhIn,
+        Register::DeviceStatus,
+        Register::DataAqi,
+        Register::DataTvoc,
+        Register::DataEtoh,
+        Register::DataEco2,
+        Register::DataT,
+        Register::DataRh,
+        Register::DataMisr,
+        Register::GprWrite,
+        Register::GprRead,
+    ];
+
+    /// register address
+    pub fn address(self) -> u8 {
+        match self {
+            Register::PartId => ENS160_PART_ID,
+            Register::OpMode => ENS160_OPMODE,
+            Register::Config => ENS160_CONFIG,
+            Register::Command => ENS160_COMMAND,
+            Register::TempIn => ENS160_TEMP_IN,
+            Register::RhIn => ENS160_RH_IN,
+            Register::DeviceStatus => ENS160_DEVICE_STATUS,
+            Register::DataAqi => ENS160_DATA_AQI,
+            Register::DataTvoc => ENS160_DATA_TVOC,
+            Register::DataEco2 => ENS160_DATA_ECO2,
+            Register::DataEtoh => ENS160_DATA_ETOH,
+            Register::DataT => ENS160_DATA_T,
+            Register::DataRh => ENS160_DATA_RH,
+            Register::DataMisr => ENS160_DATA_MISR,
+            Register::GprWrite => ENS160_GPR_WRITE,
+            Register::GprRead => ENS160_GPR_READ,
+        }
+    }
+
+    /// register size in bytes
+    pub fn size(self) -> usize {
+        match self {
+            Register::OpMode
+            | Register::Config
+            | Register::Command
+            | Register::DeviceStatus
+            | Register::DataAqi
+            | Register::DataMisr => 1,
+            Register::GprWrite | Register::GprRead => 8,
+            _ => 2,
+        }
+    }
+
+    /// register access rights
+    pub fn access(self) -> Access {
+        match self {
+            Register::OpMode
+            | Register::Config
+            | Register::Command
+            | Register::TempIn
+            | Register::RhIn
+            | Register::GprWrite => Access::ReadWrite,
+            _ => Access::ReadOnly,
+        }
+    }
+}
+
 #[repr(u8)]
 /// ENS160 I2C device address
 /// do not float the ADDR pin as its value would be undefined.  Check your ENS160 board specs.
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
 pub enum DeviceAddress {
     /// ADDR pin low
+    #[default]
     Primary = 0x52,
     /// ADDR pin high
     Secondary = 0x53,
 }
 
+/// what answered at a device address while probing the bus, see Ens160::probe()
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProbeResponse {
+    /// no device acknowledged the address
+    NoResponse,
+    /// a device answered but its part id is not an ENS160 part id
+    PartId(u16),
+}
+
 impl From<DeviceAddress> for u8 {
     fn from(value: DeviceAddress) -> Self {
         match value {
@@ -37,8 +166,3 @@ impl From<DeviceAddress> for u8 {
     }
 }
 
-impl Default for DeviceAddress {
-    fn default() -> Self {
-        Self::Primary
-    }
-}
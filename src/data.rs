@@ -1,6 +1,7 @@
 // no_std support
 #[allow(unused_imports)]
 #[warn(dead_code)]
+#[cfg(feature = "libm")]
 use libm::{exp, round, trunc};
 
 #[allow(unused_imports)] // for no_std use
@@ -9,11 +10,15 @@ use libm::{exp, round, trunc};
 //use crate::error::Error;
 use bitfield::bitfield;
 
+
 /// Default I²C address, ADDR pin low
 pub const DEFAULT_ADDRESS: u8 = 0x52;
 /// the sensor's secondary address ['SECONDARY_ADDRESS']), ADDR pin high
 pub const SECONDARY_ADDRESS: u8 = 0x53;
 
+/// number of ENS160 hot plates (sensing elements), each has its own raw resistance
+pub const HOT_PLATES: usize = 4;
+
 /// A measurement result from the sensor.
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub struct Measurements {
@@ -23,7 +28,7 @@ pub struct Measurements {
     pub tvoc_ppb: u16,
     /// air quality index as enum
     pub air_quality_index: AirQualityIndex,
-    /// ethanol concentration in ppb
+    /// ethanol concentration in ppb, 0 if not supported by the firmware
     pub etoh: u16,
     /// raw resitance value of hot plate in ohms
     pub raw_resistance: f32,
@@ -75,7 +80,7 @@ impl From<u16> for ECO2 {
 }
 
 /// Operation Mode of the sensor.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 #[repr(u8)]
 pub enum OperationMode {
     /// DEEP SLEEP mode (low-power standby)
@@ -90,14 +95,17 @@ pub enum OperationMode {
     Reset = 0xf0,
 }
 
-impl From<u8> for OperationMode {
-    fn from(value: u8) -> Self {
+/// OPMODE register value, the unknown value is the error
+impl TryFrom<u8> for OperationMode {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, u8> {
         match value {
-            0x00 => OperationMode::Sleep,
-            0x01 => OperationMode::Idle,
-            0x02 => OperationMode::Standard,
-            0xf0 => OperationMode::Reset, // just for completeness, cannot presist in this state
-            _ => unreachable!(),
+            0x00 => Ok(OperationMode::Sleep),
+            0x01 => Ok(OperationMode::Idle),
+            0x02 => Ok(OperationMode::Standard),
+            0xf0 => Ok(OperationMode::Reset), // just for completeness, cannot presist in this state
+            _ => Err(value),
         }
     }
 }
@@ -114,7 +122,7 @@ pub enum ENS160Command {
 }
 
 // required by bitfield below
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ValidityFlag {
     NormalOperation,
     WarmupPhase,
@@ -0,0 +1,247 @@
+// embedded-hal 0.2 adapters:  run the driver on HALs that only implement the 0.2
+// blocking::i2c::{Write, WriteRead} and blocking::delay::DelayMs traits
+//
+//   let mut ens160 = Ens160::new_eh02(hal_i2c, hal_delay);
+
+use core::fmt::Debug;
+
+use embedded_hal::delay::DelayNs;
+use embedded_hal::i2c::{ErrorKind, ErrorType, I2c, Operation};
+use embedded_hal_02::blocking::delay::DelayMs;
+use embedded_hal_02::blocking::i2c::{Write, WriteRead};
+
+use crate::constants::DeviceAddress;
+use crate::Ens160;
+
+/// error of an Eh02I2c bus
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Eh02Error<E> {
+    /// error of the embedded-hal 0.2 bus
+    I2c(E),
+    /// transaction the 0.2 traits can not express (anything but a write or a write-read)
+    Unsupported,
+}
+
+impl<E: Debug> embedded_hal::i2c::Error for Eh02Error<E> {
+    fn kind(&self) -> ErrorKind {
+        // embedded-hal 0.2 errors carry no kind
+        ErrorKind::Other
+    }
+}
+
+/// embedded-hal 0.2 I2C bus (Write + WriteRead) as an embedded-hal 1.0 I2c
+#[derive(Debug)]
+pub struct Eh02I2c<I2C> {
+    i2c: I2C,
+}
+
+impl<I2C> Eh02I2c<I2C> {
+    /// adapt an embedded-hal 0.2 bus
+    pub fn new(i2c: I2C) -> Self {
+        Eh02I2c { i2c }
+    }
+
+    /// give back the embedded-hal 0.2 bus
+    pub fn release(self) -> I2C {
+        self.i2c
+    }
+}
+
+impl<I2C, E> ErrorType for Eh02I2c<I2C>
+where
+    I2C: Write<Error = E> + WriteRead<Error = E>,
+    E: Debug,
+{
+    type Error = Eh02Error<E>;
+}
+
+impl<I2C, E> I2c for Eh02I2c<I2C>
+where
+    I2C: Write<Error = E> + WriteRead<Error = E>,
+    E: Debug,
+{
+    fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        match operations {
+            [Operation::Write(bytes)] => self.write(address, bytes),
+            [Operation::Write(bytes), Operation::Read(buffer)] => {
+                self.write_read(address, bytes, buffer)
+            }
+            _ => Err(Eh02Error::Unsupported),
+        }
+    }
+
+    fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.i2c.write(address, bytes).map_err(Eh02Error::I2c)
+    }
+
+    fn write_read(
+        &mut self,
+        address: u8,
+        bytes: &[u8],
+        buffer: &mut [u8],
+    ) -> Result<(), Self::Error> {
+        self.i2c
+            .write_read(address, bytes, buffer)
+            .map_err(Eh02Error::I2c)
+    }
+}
+
+/// embedded-hal 0.2 DelayMs as an embedded-hal 1.0 DelayNs, sub-millisecond delays are
+/// rounded up to whole milliseconds
+#[derive(Debug)]
+pub struct Eh02Delay<D> {
+    delay: D,
+}
+
+impl<D> Eh02Delay<D> {
+    /// adapt an embedded-hal 0.2 delay
+    pub fn new(delay: D) -> Self {
+        Eh02Delay { delay }
+    }
+
+    /// give back the embedded-hal 0.2 delay
+    pub fn release(self) -> D {
+        self.delay
+    }
+}
+
+impl<D: DelayMs<u32>> DelayNs for Eh02Delay<D> {
+    fn delay_ns(&mut self, ns: u32) {
+        self.delay.delay_ms(ns.div_ceil(1_000_000));
+    }
+
+    fn delay_us(&mut self, us: u32) {
+        self.delay.delay_ms(us.div_ceil(1_000));
+    }
+
+    fn delay_ms(&mut self, ms: u32) {
+        self.delay.delay_ms(ms);
+    }
+}
+
+impl<I2C, D, E> Ens160<Eh02I2c<I2C>, Eh02Delay<D>>
+where
+    I2C: Write<Error = E> + WriteRead<Error = E>,
+    E: Debug,
+    D: DelayMs<u32>,
+{
+    /// create new ENS160 driver on an embedded-hal 0.2 bus and delay, default I2C address
+    pub fn new_eh02(i2c: I2C, delay: D) -> Self {
+        Self::new(Eh02I2c::new(i2c), Eh02Delay::new(delay))
+    }
+
+    /// create new ENS160 driver on an embedded-hal 0.2 bus and delay with the given I2C address
+    pub fn new_eh02_with_address(i2c: I2C, delay: D, address: DeviceAddress) -> Self {
+        Self::new_with_address(Eh02I2c::new(i2c), Eh02Delay::new(delay), address)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::{ENS160_PART_ID, ENS160_TEMP_IN};
+
+    // embedded-hal 0.2 bus recording (address, bytes written, bytes read)
+    #[derive(Default)]
+    struct Bus02 {
+        calls: Vec<(u8, Vec<u8>, usize)>,
+        fail: bool,
+    }
+
+    impl Write for Bus02 {
+        type Error = u8;
+
+        fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), u8> {
+            self.calls.push((address, bytes.to_vec(), 0));
+            if self.fail {
+                Err(7)
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    impl WriteRead for Bus02 {
+        type Error = u8;
+
+        fn write_read(&mut self, address: u8, bytes: &[u8], buffer: &mut [u8]) -> Result<(), u8> {
+            self.calls.push((address, bytes.to_vec(), buffer.len()));
+            // part id 0x0160, little endian
+            buffer.copy_from_slice(&[0x60, 0x01][..buffer.len()]);
+            if self.fail {
+                Err(7)
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    // embedded-hal 0.2 delay recording the requested milliseconds
+    #[derive(Default)]
+    struct Delay02 {
+        delays_ms: Vec<u32>,
+    }
+
+    impl DelayMs<u32> for Delay02 {
+        fn delay_ms(&mut self, ms: u32) {
+            self.delays_ms.push(ms);
+        }
+    }
+
+    #[test]
+    fn write_and_write_read_are_forwarded() {
+        let mut ens160 = Ens160::new_eh02(Bus02::default(), Delay02::default());
+        assert_eq!(ens160.get_part_id().unwrap(), 0x0160);
+        ens160.set_temp_rh_comp(25.0, 50.0).unwrap();
+        let bus = ens160.release().release();
+        assert_eq!(bus.calls[0], (0x52, vec![ENS160_PART_ID], 2));
+        assert_eq!(bus.calls[1], (0x52, vec![ENS160_TEMP_IN, 0x8a, 0x4a], 0));
+
+        let mut i2c = Eh02I2c::new(Bus02 {
+            fail: true,
+            ..Default::default()
+        });
+        assert_eq!(i2c.write(0x53, &[1]), Err(Eh02Error::I2c(7)));
+        assert_eq!(
+            i2c.write_read(0x53, &[1], &mut [0; 2]),
+            Err(Eh02Error::I2c(7))
+        );
+    }
+
+    #[test]
+    fn other_transactions_are_unsupported() {
+        let mut i2c = Eh02I2c::new(Bus02::default());
+        let mut buffer = [0u8; 2];
+        assert_eq!(i2c.read(0x52, &mut buffer), Err(Eh02Error::Unsupported));
+        assert_eq!(
+            i2c.transaction(0x52, &mut [Operation::Write(&[1]), Operation::Write(&[2])]),
+            Err(Eh02Error::Unsupported)
+        );
+        assert_eq!(
+            i2c.transaction(
+                0x52,
+                &mut [Operation::Read(&mut buffer), Operation::Write(&[1])]
+            ),
+            Err(Eh02Error::Unsupported)
+        );
+        assert_eq!(i2c.transaction(0x52, &mut []), Err(Eh02Error::Unsupported));
+        assert!(i2c.release().calls.is_empty());
+    }
+
+    #[test]
+    fn sub_millisecond_delays_round_up() {
+        let mut delay = Eh02Delay::new(Delay02::default());
+        delay.delay_ns(0);
+        delay.delay_ns(1);
+        delay.delay_ns(1_000_000);
+        delay.delay_ns(1_000_001);
+        delay.delay_us(1);
+        delay.delay_us(2_500);
+        delay.delay_ms(5);
+        assert_eq!(delay.release().delays_ms, [0, 1, 1, 2, 1, 3, 5]);
+    }
+}
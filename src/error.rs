@@ -1,43 +1,303 @@
 // 2024.09.02 updated this error enum to a more modern Error style
-//use core::fmt::Formatter;
 
-//use embedded_hal::i2c::{I2c, SevenBitAddress};
+use core::fmt::{self, Debug, Display, Formatter};
+
+use crate::constants::{ProbeResponse, Register};
+use crate::firmware::FirmwareVersion;
+
+#[cfg(not(feature = "async"))]
+use embedded_hal::i2c::{Error as I2cError, ErrorKind, NoAcknowledgeSource};
+#[cfg(feature = "async")]
+use embedded_hal_async::i2c::{Error as I2cError, ErrorKind, NoAcknowledgeSource};
+
+/// the kind of I2C transaction that failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum I2cOp {
+    /// register write (register address followed by data)
+    Write,
+    /// register read (register address write followed by a read)
+    Read,
+}
+
+/// how a failed call should be handled, see Error::classify()
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorClass {
+    /// the same call may succeed later (bus glitch, sensor resetting), worth retrying
+    Transient,
+    /// no device answers at the address (not connected, not powered or wrong address)
+    DeviceMissing,
+    /// fails again on every retry (wrong device, firmware, sensor state or register access)
+    Permanent,
+}
 
 /// All possible errors
-/// Display not implemented for no_std support
 #[derive(Clone, Copy, Debug)]
-pub enum Error<E>
-//where
-//    I2c: I2c<SevenBitAddress>
-{
-    /// Error during I2C write/read operation.
-    I2c(E),
-    //// Error during I2C write operation.
-    //WriteError(I2C::Error),
-    //// Error during I2C WriteRead operation.
-    //WriteReadError(I2C::Error),
+pub enum Error<E> {
+    /// Error during I2C write/read operation, with the register it was addressing
+    I2c {
+        /// write or read
+        op: I2cOp,
+        /// register address
+        register: u8,
+        /// error of the I2C bus implementation
+        source: E,
+    },
     /// Got an unexpected Part Id during sensor initalization.
     UnexpectedChipId(u16),
+    /// no ENS160 found by probe(), with what answered at the primary and secondary address
+    DeviceNotFound(ProbeResponse, ProbeResponse),
     /// unexpected Operation Mode
     OpModeNotCorrect(u8),
+    /// command needs idle mode, the sensor is in the given operating mode
+    NotIdle(u8),
+    /// firmware is older than the configured minimum
+    UnsupportedFirmware(FirmwareVersion),
+    /// feature is not available with the sensor's firmware version
+    NotSupportedByFirmware(FirmwareVersion),
+    /// interrupt pin config register read back differs from the value written
+    InterruptConfigNotCorrect(u8),
+    /// register (address) is read only
+    RegisterReadOnly(u8),
+    /// buffer length does not fit the register (address, length)
+    InvalidRegisterLength(u8, usize),
+}
+
+impl<E> Error<E> {
+    /// true if the same call may succeed later:  I2C errors (bus glitches, a sensor that
+    /// is resetting) and read back mismatches after a sensor reset.  Permanent errors
+    /// (wrong device, firmware, invalid register access) fail again on every retry.
+    /// A sensor missing from the bus also shows as a transient address NoAcknowledge,
+    /// classify() tells it apart when the bus error kind is known.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Error::I2c { .. }
+            | Error::OpModeNotCorrect(_)
+            | Error::InterruptConfigNotCorrect(_) => true,
+            Error::UnexpectedChipId(_)
+            | Error::DeviceNotFound(_, _)
+            | Error::NotIdle(_)
+            | Error::UnsupportedFirmware(_)
+            | Error::NotSupportedByFirmware(_)
+            | Error::RegisterReadOnly(_)
+            | Error::InvalidRegisterLength(_, _) => false,
+        }
+    }
+}
+
+impl<E: I2cError> Error<E> {
+    /// embedded-hal ErrorKind of an I2C error (NoAcknowledge, ArbitrationLoss, Bus, ...),
+    /// None for errors detected by the driver itself
+    pub fn i2c_kind(&self) -> Option<ErrorKind> {
+        match self {
+            Error::I2c { source, .. } => Some(source.kind()),
+            _ => None,
+        }
+    }
+
+    /// classify by the embedded-hal ErrorKind:  arbitration loss, bus errors, overruns and
+    /// other or unknown bus errors are transient, an address NoAcknowledge is a missing
+    /// device.  Driver errors are classified like is_transient().
+    pub fn classify(&self) -> ErrorClass {
+        match self {
+            Error::I2c { source, .. } => match source.kind() {
+                ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address) => {
+                    ErrorClass::DeviceMissing
+                }
+                // a data NoAcknowledge is the sensor refusing a byte, e.g. while it is busy
+                _ => ErrorClass::Transient,
+            },
+            Error::DeviceNotFound(_, _) => ErrorClass::DeviceMissing,
+            _ if self.is_transient() => ErrorClass::Transient,
+            _ => ErrorClass::Permanent,
+        }
+    }
 }
 
-//impl<I2C> core::fmt::Debug for Error<I2C>
-//where
-//    I2C: I2c<SevenBitAddress>
-//{
-//    fn fmt(&self, f: &mut Formatter<'_>) -> core::result::Result<(), core::fmt::Error> {
-//        match self {
-//            Error::WriteReadError(e) => f.debug_tuple("WriteReadError").field(e).finish(),
-//            Error::WriteError(e) => f.debug_tuple("WriteError").field(e).finish(),
-//            Error::UnexpectedChipId(chip_id) => f
-//                .debug_tuple("Expected part id 352, got : ")
-//                .field(chip_id)
-//                .finish(),
-//            Error::OpModeNotCorrect(expected) => f
-//                .debug_tuple("Incorrect ENS160 operation, got :")
-//                .field(expected)
-//                .finish(),
-//        }
-//    }
-//}
+impl<E: Debug> Display for Error<E> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::I2c {
+                op,
+                register,
+                source,
+            } => {
+                let op = match op {
+                    I2cOp::Write => "write",
+                    I2cOp::Read => "read",
+                };
+                write!(
+                    f,
+                    "I2C {} of register {} failed: {:?}",
+                    op,
+                    RegisterName(*register),
+                    source
+                )
+            }
+            Error::UnexpectedChipId(part_id) => {
+                write!(
+                    f,
+                    "unexpected part id {:#06x}, expected 0x0160 or 0x0161",
+                    part_id
+                )
+            }
+            Error::DeviceNotFound(primary, secondary) => write!(
+                f,
+                "no ENS160 found: {} at 0x52, {} at 0x53",
+                ProbeName(*primary),
+                ProbeName(*secondary)
+            ),
+            Error::OpModeNotCorrect(mode) => {
+                write!(
+                    f,
+                    "sensor is in operating mode {:#04x}, not the expected one",
+                    mode
+                )
+            }
+            Error::NotIdle(mode) => {
+                write!(
+                    f,
+                    "command needs idle mode, sensor is in operating mode {:#04x}",
+                    mode
+                )
+            }
+            Error::UnsupportedFirmware(version) => {
+                write!(
+                    f,
+                    "firmware {} is older than the configured minimum",
+                    version
+                )
+            }
+            Error::NotSupportedByFirmware(version) => {
+                write!(f, "not supported by firmware {}", version)
+            }
+            Error::InterruptConfigNotCorrect(read_back) => {
+                write!(f, "interrupt pin config read back as {:#04x}", read_back)
+            }
+            Error::RegisterReadOnly(address) => {
+                write!(f, "register {} is read only", RegisterName(*address))
+            }
+            Error::InvalidRegisterLength(address, length) => write!(
+                f,
+                "{} bytes do not fit register {}",
+                length,
+                RegisterName(*address)
+            ),
+        }
+    }
+}
+
+impl<E: Debug> core::error::Error for Error<E> {}
+
+// register address with its name, e.g. "0x20 (DeviceStatus)"
+struct RegisterName(u8);
+
+impl Display for RegisterName {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#04x}", self.0)?;
+        match Register::ALL
+            .iter()
+            .find(|register| register.address() == self.0)
+        {
+            Some(register) => write!(f, " ({:?})", register),
+            None => Ok(()),
+        }
+    }
+}
+
+struct ProbeName(ProbeResponse);
+
+impl Display for ProbeName {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            ProbeResponse::NoResponse => write!(f, "no response"),
+            ProbeResponse::PartId(part_id) => write!(f, "part id {:#06x}", part_id),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug)]
+    struct BusError(ErrorKind);
+
+    impl I2cError for BusError {
+        fn kind(&self) -> ErrorKind {
+            self.0
+        }
+    }
+
+    fn bus_error(kind: ErrorKind) -> Error<BusError> {
+        Error::I2c {
+            op: I2cOp::Read,
+            register: 0x20,
+            source: BusError(kind),
+        }
+    }
+
+    #[test]
+    fn i2c_errors_by_kind() {
+        for kind in [
+            ErrorKind::ArbitrationLoss,
+            ErrorKind::Bus,
+            ErrorKind::Overrun,
+            ErrorKind::NoAcknowledge(NoAcknowledgeSource::Data),
+            ErrorKind::NoAcknowledge(NoAcknowledgeSource::Unknown),
+            ErrorKind::Other,
+        ] {
+            assert_eq!(
+                bus_error(kind).classify(),
+                ErrorClass::Transient,
+                "{:?}",
+                kind
+            );
+            assert_eq!(bus_error(kind).i2c_kind(), Some(kind));
+        }
+        let missing = bus_error(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address));
+        assert_eq!(missing.classify(), ErrorClass::DeviceMissing);
+        assert!(missing.is_transient());
+    }
+
+    #[test]
+    fn driver_errors() {
+        let transient: [Error<BusError>; 2] = [
+            Error::OpModeNotCorrect(0x00),
+            Error::InterruptConfigNotCorrect(0x00),
+        ];
+        for error in transient {
+            assert!(error.is_transient());
+            assert_eq!(error.classify(), ErrorClass::Transient);
+            assert_eq!(error.i2c_kind(), None);
+        }
+        let not_found: Error<BusError> =
+            Error::DeviceNotFound(ProbeResponse::NoResponse, ProbeResponse::NoResponse);
+        assert!(!not_found.is_transient());
+        assert_eq!(not_found.classify(), ErrorClass::DeviceMissing);
+        let permanent: [Error<BusError>; 6] = [
+            Error::UnexpectedChipId(0x1234),
+            Error::NotIdle(0x02),
+            Error::UnsupportedFirmware(FirmwareVersion::TESTED),
+            Error::NotSupportedByFirmware(FirmwareVersion::TESTED),
+            Error::RegisterReadOnly(0x00),
+            Error::InvalidRegisterLength(0x00, 3),
+        ];
+        for error in permanent {
+            assert!(!error.is_transient());
+            assert_eq!(error.classify(), ErrorClass::Permanent);
+        }
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(
+            format!("{}", bus_error(ErrorKind::Bus)),
+            "I2C read of register 0x20 (DeviceStatus) failed: BusError(Bus)"
+        );
+        let not_idle: Error<BusError> = Error::NotIdle(0x02);
+        assert_eq!(
+            format!("{}", not_idle),
+            "command needs idle mode, sensor is in operating mode 0x02"
+        );
+    }
+}
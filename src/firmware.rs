@@ -0,0 +1,107 @@
+// ENS160 firmware version and the behaviour differences between firmware releases
+
+use core::fmt;
+
+/// ENS160 firmware (application) version, ordered by major, minor then release
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FirmwareVersion {
+    pub major: u8,
+    pub minor: u8,
+    pub release: u8,
+}
+
+impl FirmwareVersion {
+    /// the firmware version this library was tested with
+    pub const TESTED: FirmwareVersion = FirmwareVersion::new(5, 4, 6);
+
+    /// create a firmware version major.minor.release
+    pub const fn new(major: u8, minor: u8, release: u8) -> Self {
+        FirmwareVersion {
+            major,
+            minor,
+            release,
+        }
+    }
+
+    /// behaviour differences the driver has to handle for this firmware version
+    pub fn quirks(&self) -> Quirks {
+        QUIRK_TABLE
+            .iter()
+            .rev()
+            .find(|(first_version, _)| self >= first_version)
+            .map(|(_, quirks)| *quirks)
+            .unwrap_or(DEFAULT_QUIRKS)
+    }
+}
+
+impl fmt::Display for FirmwareVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.release)
+    }
+}
+
+impl From<(u8, u8, u8)> for FirmwareVersion {
+    fn from((major, minor, release): (u8, u8, u8)) -> Self {
+        FirmwareVersion::new(major, minor, release)
+    }
+}
+
+impl From<FirmwareVersion> for (u8, u8, u8) {
+    fn from(version: FirmwareVersion) -> Self {
+        (version.major, version.minor, version.release)
+    }
+}
+
+/// firmware dependent behaviour, applied by the driver so callers don't have to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quirks {
+    /// DATA_ETOH holds a usable ethanol concentration
+    pub etoh_available: bool,
+    /// extra delay in ms after a command before its GPR_READ result is read
+    pub command_delay_ms: u32,
+}
+
+impl Default for Quirks {
+    // datasheet behaviour, used until the version has been read
+    fn default() -> Self {
+        DEFAULT_QUIRKS
+    }
+}
+
+const DEFAULT_QUIRKS: Quirks = Quirks {
+    etoh_available: true,
+    command_delay_ms: 0,
+};
+
+// first firmware version each entry applies to, in ascending order.  Versions before the
+// first entry get DEFAULT_QUIRKS.  Empty:  no firmware dependent behaviour is documented
+// yet, only add entries backed by a datasheet, application note or errata.
+const QUIRK_TABLE: &[(FirmwareVersion, Quirks)] = &[];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ordering_and_display() {
+        assert!(FirmwareVersion::new(5, 4, 6) > FirmwareVersion::new(5, 4, 5));
+        assert!(FirmwareVersion::new(5, 10, 0) > FirmwareVersion::new(5, 4, 6));
+        assert!(FirmwareVersion::new(6, 0, 0) > FirmwareVersion::new(5, 255, 255));
+        assert_eq!(FirmwareVersion::from((5, 4, 6)), FirmwareVersion::TESTED);
+        assert_eq!(format!("{}", FirmwareVersion::TESTED), "5.4.6");
+    }
+
+    #[test]
+    fn all_versions_get_datasheet_behaviour() {
+        for version in [
+            FirmwareVersion::new(0, 0, 0),
+            FirmwareVersion::new(5, 4, 5),
+            FirmwareVersion::TESTED,
+            FirmwareVersion::new(255, 255, 255),
+        ] {
+            assert_eq!(version.quirks(), Quirks::default());
+        }
+        assert!(Quirks::default().etoh_available);
+        assert_eq!(Quirks::default().command_delay_ms, 0);
+    }
+}
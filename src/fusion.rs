@@ -0,0 +1,399 @@
+// fusion of redundant ENS160 sensors (e.g. two or three in the same duct)
+
+use crate::data::{Measurements, ValidityFlag};
+use crate::health::Severity;
+
+/// one sensor's reading for SensorFusion::fuse()
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FusionInput {
+    /// the sensor's measurements
+    pub measurements: Measurements,
+    /// validity flag from the sensor's status register
+    pub validity: ValidityFlag,
+    /// health of the sensor, Critical sensors are not used
+    pub health: Severity,
+}
+
+impl FusionInput {
+    /// input from measurements and the validity flag of the same reading, assumed healthy
+    pub fn new(measurements: Measurements, validity: ValidityFlag) -> Self {
+        FusionInput {
+            measurements,
+            validity,
+            health: Severity::Ok,
+        }
+    }
+
+    /// set the sensor health, e.g. HealthReport::severity
+    pub fn health(mut self, health: Severity) -> Self {
+        self.health = health;
+        self
+    }
+
+    // weight of this reading, 0.0 means not usable
+    fn weight(&self) -> f32 {
+        if self.health == Severity::Critical {
+            return 0.0;
+        }
+        let weight = match self.validity {
+            ValidityFlag::NormalOperation => 1.0,
+            ValidityFlag::InitialStartupPhase => 0.5,
+            ValidityFlag::WarmupPhase | ValidityFlag::InvalidOutput => 0.0,
+        };
+        if self.health == Severity::Warning {
+            weight * 0.5
+        } else {
+            weight
+        }
+    }
+}
+
+/// limits used by SensorFusion
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FusionConfig {
+    /// eCO2 further than this (ppm) from the median is an outlier
+    pub max_eco2_deviation_ppm: u16,
+    /// TVOC further than this (ppb) from the median is an outlier
+    pub max_tvoc_deviation_ppb: u16,
+    /// consecutive outlier readings after which a sensor is flagged as diverging
+    pub divergence_limit: u8,
+}
+
+impl Default for FusionConfig {
+    fn default() -> Self {
+        FusionConfig {
+            max_eco2_deviation_ppm: 200,
+            max_tvoc_deviation_ppb: 150,
+            divergence_limit: 5,
+        }
+    }
+}
+
+/// fused estimate of N redundant sensors
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FusedEstimate<const N: usize> {
+    /// fused CO₂ equivalent in ppm
+    pub eco2_ppm: u16,
+    /// fused TVOC in ppb
+    pub tvoc_ppb: u16,
+    /// 0.0 (no agreement) to 1.0 (all sensors valid, healthy and agreeing)
+    pub confidence: f32,
+    /// sensors used for the estimate
+    pub used: [bool; N],
+    /// sensors that have diverged from the others for divergence_limit readings in a row
+    pub diverging: [bool; N],
+}
+
+/// Combines the readings of N redundant sensors:  readings that are invalid (warm-up,
+/// invalid output) or from Critical sensors are skipped, the rest are compared with the
+/// median and outliers are rejected, the remaining readings are averaged weighted by their
+/// validity and health.  With only two sensors an outlier can not be attributed to one of
+/// them, both are rejected and the confidence drops.
+#[derive(Debug, Clone, Copy)]
+pub struct SensorFusion<const N: usize> {
+    config: FusionConfig,
+    outlier_counts: [u8; N],
+}
+
+impl<const N: usize> Default for SensorFusion<N> {
+    fn default() -> Self {
+        Self::new(FusionConfig::default())
+    }
+}
+
+impl<const N: usize> SensorFusion<N> {
+    /// create a new fusion of N sensors
+    pub fn new(config: FusionConfig) -> Self {
+        SensorFusion {
+            config,
+            outlier_counts: [0; N],
+        }
+    }
+
+    /// sensors currently flagged as diverging
+    pub fn diverging(&self) -> [bool; N] {
+        self.outlier_counts
+            .map(|count| count >= self.config.divergence_limit)
+    }
+
+    /// fuse one reading per sensor (None if the sensor could not be read).
+    /// Returns None if no sensor has a usable reading.  A sensor without a usable reading
+    /// (not read, warming up, Critical) loses its diverging flag:  it can not be compared
+    /// with the others, its outlier count starts again when it is usable.
+    pub fn fuse(&mut self, inputs: &[Option<FusionInput>; N]) -> Option<FusedEstimate<N>> {
+        let weights = inputs.map(|input| input.map_or(0.0, |input| input.weight()));
+        for (outlier_count, weight) in self.outlier_counts.iter_mut().zip(weights) {
+            if weight <= 0.0 {
+                *outlier_count = 0;
+            }
+        }
+
+        let mut eco2_values = [0u16; N];
+        let mut tvoc_values = [0u16; N];
+        let mut count = 0;
+        for (input, weight) in inputs.iter().zip(weights) {
+            if let (Some(input), true) = (input, weight > 0.0) {
+                eco2_values[count] = input.measurements.co2eq_ppm.get_value();
+                tvoc_values[count] = input.measurements.tvoc_ppb;
+                count += 1;
+            }
+        }
+        if count == 0 {
+            return None;
+        }
+        let eco2_median = median(&mut eco2_values[..count]);
+        let tvoc_median = median(&mut tvoc_values[..count]);
+
+        let mut used = [false; N];
+        let mut eco2_sum = 0.0;
+        let mut tvoc_sum = 0.0;
+        let mut weight_sum = 0.0;
+        for (index, (input, weight)) in inputs.iter().zip(weights).enumerate() {
+            let Some(input) = input.filter(|_| weight > 0.0) else {
+                continue;
+            };
+            let eco2 = input.measurements.co2eq_ppm.get_value();
+            let tvoc = input.measurements.tvoc_ppb;
+            let outlier = eco2.abs_diff(eco2_median) > self.config.max_eco2_deviation_ppm
+                || tvoc.abs_diff(tvoc_median) > self.config.max_tvoc_deviation_ppb;
+            if outlier {
+                self.outlier_counts[index] = self.outlier_counts[index].saturating_add(1);
+            } else {
+                self.outlier_counts[index] = 0;
+                used[index] = true;
+                eco2_sum += weight * eco2 as f32;
+                tvoc_sum += weight * tvoc as f32;
+                weight_sum += weight;
+            }
+        }
+
+        let (eco2_ppm, tvoc_ppb) = if weight_sum > 0.0 {
+            (
+                (eco2_sum / weight_sum + 0.5) as u16,
+                (tvoc_sum / weight_sum + 0.5) as u16,
+            )
+        } else {
+            (eco2_median, tvoc_median)
+        };
+        Some(FusedEstimate {
+            eco2_ppm,
+            tvoc_ppb,
+            confidence: weight_sum / N as f32,
+            used,
+            diverging: self.diverging(),
+        })
+    }
+}
+
+// median of a non empty slice, the two middle values are averaged for an even count
+fn median(values: &mut [u16]) -> u16 {
+    values.sort_unstable();
+    let middle = values.len() / 2;
+    if middle * 2 == values.len() {
+        ((values[middle - 1] as u32 + values[middle] as u32) / 2) as u16
+    } else {
+        values[middle]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::AirQualityIndex;
+    use crate::units::resistance_from_raw;
+
+    const NORMAL: ValidityFlag = ValidityFlag::NormalOperation;
+    const STARTUP: ValidityFlag = ValidityFlag::InitialStartupPhase;
+    const WARMUP: ValidityFlag = ValidityFlag::WarmupPhase;
+
+    fn input(eco2: u16, tvoc: u16, validity: ValidityFlag, health: Severity) -> FusionInput {
+        let measurements = Measurements {
+            co2eq_ppm: eco2.into(),
+            tvoc_ppb: tvoc,
+            air_quality_index: AirQualityIndex::Excellent,
+            etoh: 0,
+            raw_resistance: resistance_from_raw(0),
+        };
+        FusionInput::new(measurements, validity).health(health)
+    }
+
+    fn ok(eco2: u16, tvoc: u16) -> Option<FusionInput> {
+        Some(input(eco2, tvoc, NORMAL, Severity::Ok))
+    }
+
+    #[test]
+    fn median_of_values() {
+        let cases: [(&[u16], u16); 5] = [
+            (&[7], 7),
+            (&[9, 1, 5], 5),
+            (&[400, 600], 500),
+            (&[4, 1, 3, 2], 2),
+            (&[u16::MAX, u16::MAX], u16::MAX),
+        ];
+        for (values, expected) in cases {
+            assert_eq!(median(&mut values.to_vec()), expected, "{:?}", values);
+        }
+    }
+
+    #[test]
+    fn weights() {
+        let cases = [
+            (NORMAL, Severity::Ok, 1.0),
+            (NORMAL, Severity::Warning, 0.5),
+            (NORMAL, Severity::Critical, 0.0),
+            (STARTUP, Severity::Ok, 0.5),
+            (STARTUP, Severity::Warning, 0.25),
+            (WARMUP, Severity::Ok, 0.0),
+            (ValidityFlag::InvalidOutput, Severity::Ok, 0.0),
+        ];
+        for (validity, health, expected) in cases {
+            let weight = input(400, 0, validity, health).weight();
+            assert_eq!(weight, expected, "{:?} {:?}", validity, health);
+        }
+    }
+
+    // (inputs, eco2, tvoc, confidence, used)
+    type Case = ([Option<FusionInput>; 3], u16, u16, f32, [bool; 3]);
+
+    #[test]
+    fn fuse_three_sensors() {
+        let cases: [Case; 6] = [
+            // all agree:  plain average
+            (
+                [ok(500, 100), ok(520, 110), ok(540, 120)],
+                520,
+                110,
+                1.0,
+                [true; 3],
+            ),
+            // one eCO2 outlier is rejected
+            (
+                [ok(500, 100), ok(520, 100), ok(900, 100)],
+                510,
+                100,
+                2.0 / 3.0,
+                [true, true, false],
+            ),
+            // one TVOC outlier is rejected
+            (
+                [ok(500, 100), ok(500, 400), ok(500, 120)],
+                500,
+                110,
+                2.0 / 3.0,
+                [true, false, true],
+            ),
+            // a missing sensor
+            (
+                [ok(500, 100), None, ok(600, 200)],
+                550,
+                150,
+                2.0 / 3.0,
+                [true, false, true],
+            ),
+            // warm-up and Critical sensors are skipped
+            (
+                [
+                    ok(500, 100),
+                    Some(input(900, 900, WARMUP, Severity::Ok)),
+                    Some(input(900, 900, NORMAL, Severity::Critical)),
+                ],
+                500,
+                100,
+                1.0 / 3.0,
+                [true, false, false],
+            ),
+            // weighted by validity and health:  (500 * 1 + 800 * 0.5 + 600 * 0.5) / 2
+            (
+                [
+                    ok(500, 100),
+                    Some(input(800, 100, STARTUP, Severity::Ok)),
+                    Some(input(600, 100, NORMAL, Severity::Warning)),
+                ],
+                600,
+                100,
+                2.0 / 3.0,
+                [true, true, true],
+            ),
+        ];
+        for (index, (inputs, eco2, tvoc, confidence, used)) in cases.iter().enumerate() {
+            let mut fusion = SensorFusion::<3>::default();
+            let estimate = fusion.fuse(inputs).unwrap();
+            assert_eq!(estimate.eco2_ppm, *eco2, "case {}", index);
+            assert_eq!(estimate.tvoc_ppb, *tvoc, "case {}", index);
+            assert!(
+                (estimate.confidence - confidence).abs() < 1e-6,
+                "case {}: {}",
+                index,
+                estimate.confidence
+            );
+            assert_eq!(estimate.used, *used, "case {}", index);
+            assert_eq!(estimate.diverging, [false; 3], "case {}", index);
+        }
+    }
+
+    #[test]
+    fn two_sensors_disagreeing_are_both_rejected() {
+        let mut fusion = SensorFusion::<2>::default();
+        // within the limits of the median (500):  both used
+        let estimate = fusion.fuse(&[ok(400, 100), ok(600, 100)]).unwrap();
+        assert_eq!(estimate.used, [true, true]);
+        assert_eq!(estimate.confidence, 1.0);
+        // too far apart:  the median is reported with no confidence
+        let estimate = fusion.fuse(&[ok(400, 100), ok(1000, 100)]).unwrap();
+        assert_eq!(estimate.used, [false, false]);
+        assert_eq!(estimate.eco2_ppm, 700);
+        assert_eq!(estimate.confidence, 0.0);
+    }
+
+    #[test]
+    fn no_usable_reading() {
+        let mut fusion = SensorFusion::<2>::default();
+        assert_eq!(fusion.fuse(&[None, None]), None);
+        let warming_up = Some(input(500, 100, WARMUP, Severity::Ok));
+        assert_eq!(fusion.fuse(&[warming_up, None]), None);
+    }
+
+    #[test]
+    fn divergence_limit() {
+        let config = FusionConfig {
+            divergence_limit: 3,
+            ..Default::default()
+        };
+        let mut fusion = SensorFusion::<3>::new(config);
+        let diverging = [ok(500, 100), ok(510, 100), ok(1500, 100)];
+        for reading in 1..=3 {
+            let estimate = fusion.fuse(&diverging).unwrap();
+            assert_eq!(estimate.diverging, [false, false, reading == 3]);
+        }
+        assert_eq!(fusion.diverging(), [false, false, true]);
+        // stays flagged while it diverges, cleared by the first agreeing reading
+        assert!(fusion.fuse(&diverging).unwrap().diverging[2]);
+        let agreeing = [ok(500, 100), ok(510, 100), ok(520, 100)];
+        assert_eq!(fusion.fuse(&agreeing).unwrap().diverging, [false; 3]);
+    }
+
+    #[test]
+    fn unusable_sensor_loses_divergence() {
+        let config = FusionConfig {
+            divergence_limit: 2,
+            ..Default::default()
+        };
+        let diverging = [ok(500, 100), ok(510, 100), ok(1500, 100)];
+        let unusable = [
+            None,
+            Some(input(1500, 100, WARMUP, Severity::Ok)),
+            Some(input(1500, 100, NORMAL, Severity::Critical)),
+        ];
+        for sensor in unusable {
+            let mut fusion = SensorFusion::<3>::new(config);
+            fusion.fuse(&diverging);
+            fusion.fuse(&diverging);
+            assert_eq!(fusion.diverging(), [false, false, true]);
+            let estimate = fusion.fuse(&[ok(500, 100), ok(510, 100), sensor]).unwrap();
+            assert_eq!(estimate.diverging, [false; 3]);
+            // the count starts again:  one more outlier is not enough to flag it
+            assert_eq!(fusion.fuse(&diverging).unwrap().diverging, [false; 3]);
+            assert!(fusion.fuse(&diverging).unwrap().diverging[2]);
+        }
+    }
+}
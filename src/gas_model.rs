@@ -0,0 +1,98 @@
+// custom gas models computed from the raw hot plate resistances
+
+use crate::baseline::BaselineEstimator;
+use crate::data::HOT_PLATES;
+
+/// input values handed to a GasModel, taken from one set of sensor readings
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GasModelInput {
+    /// raw resistance of each hot plate in ohms
+    pub resistances_ohm: [f32; HOT_PLATES],
+    /// compensation temperature in degrees C (as read back from the ENS160)
+    pub temp_c: f32,
+    /// compensation relative humidity in percent (as read back from the ENS160)
+    pub rh_percent: f32,
+    /// clean air resistance of each hot plate in ohms, if known by the caller.
+    /// None lets the model use its own baseline
+    pub baseline_ohm: Option<[f32; HOT_PLATES]>,
+}
+
+/// A custom gas model:  derives your own quantities from the raw hot plate resistances,
+/// alongside the ENS160's own eCO2, TVOC and AQI values.
+pub trait GasModel {
+    /// the derived quantities
+    type Output;
+
+    /// feed one set of readings into the model and get the derived quantities back
+    fn update(&mut self, input: &GasModelInput) -> Self::Output;
+}
+
+/// output of the BaselineRatioModel
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GasRatios {
+    /// Rs/R0 for each hot plate:  1.0 is clean air, lower values mean more reducing gases (VOCs)
+    pub ratios: [f32; HOT_PLATES],
+    /// baseline (R0) in ohms used for each ratio
+    pub baselines_ohm: [f32; HOT_PLATES],
+}
+
+/// Reference gas model:  baseline ratio Rs/R0 with slow baseline tracking.
+/// The baseline is kept by a BaselineEstimator, which follows a rising resistance
+/// (cleaner air) quickly and a falling resistance (polluted air) very slowly, so short gas
+/// events do not drag the baseline down.  Temperature and humidity are not used by this model.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BaselineRatioModel {
+    estimator: BaselineEstimator,
+}
+
+impl BaselineRatioModel {
+    /// create a new model, the first update sets the baseline.
+    /// rise_alpha and fall_alpha (0.0 to 1.0) are the per update filter weights used when
+    /// the resistance is above or below the baseline
+    pub fn new(rise_alpha: f32, fall_alpha: f32) -> Self {
+        BaselineRatioModel {
+            estimator: BaselineEstimator::new(rise_alpha, fall_alpha),
+        }
+    }
+
+    /// create a new model using an existing (e.g. restored from flash) baseline estimator
+    pub fn with_estimator(estimator: BaselineEstimator) -> Self {
+        BaselineRatioModel { estimator }
+    }
+
+    /// the baseline estimator, save its state with estimator().save()
+    pub fn estimator(&self) -> &BaselineEstimator {
+        &self.estimator
+    }
+
+    /// current baseline of each hot plate in ohms
+    pub fn baselines_ohm(&self) -> [f32; HOT_PLATES] {
+        self.estimator.baselines_ohm()
+    }
+}
+
+impl GasModel for BaselineRatioModel {
+    type Output = GasRatios;
+
+    fn update(&mut self, input: &GasModelInput) -> GasRatios {
+        let baselines_ohm = match input.baseline_ohm {
+            Some(baseline) => {
+                self.estimator.seed(baseline);
+                baseline
+            }
+            None => self.estimator.update(&input.resistances_ohm),
+        };
+
+        let mut ratios = [0.0; HOT_PLATES];
+        for (ratio, (rs, r0)) in ratios
+            .iter_mut()
+            .zip(input.resistances_ohm.iter().zip(baselines_ohm.iter()))
+        {
+            *ratio = if *r0 > 0.0 { rs / r0 } else { 0.0 };
+        }
+        GasRatios {
+            ratios,
+            baselines_ohm,
+        }
+    }
+}
@@ -0,0 +1,262 @@
+// sensor health monitoring and fault diagnostics
+
+use crate::config::Variant;
+use crate::error::Error;
+use crate::firmware::FirmwareVersion;
+
+/// how bad a health finding is, ordered from Ok to Critical
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum Severity {
+    /// everything looks fine
+    #[default]
+    Ok,
+    /// worth logging, readings are still usable
+    Warning,
+    /// the sensor is failing or not responding, readings should not be trusted
+    Critical,
+}
+
+/// limits used by the HealthMonitor, the defaults are reasonable for indoor use
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HealthThresholds {
+    /// eCO2 unchanged for this long (in ms) is reported as stuck, default two hours
+    pub stuck_eco2_ms: u32,
+    /// lowest plausible hot plate resistance in ohms
+    pub min_resistance_ohm: f32,
+    /// highest plausible hot plate resistance in ohms
+    pub max_resistance_ohm: f32,
+    /// I2C error rate (in percent of transactions) above which a warning is raised
+    pub max_i2c_error_percent: u8,
+    /// oldest acceptable firmware version, None to skip the check
+    pub min_firmware: Option<FirmwareVersion>,
+}
+
+impl Default for HealthThresholds {
+    fn default() -> Self {
+        HealthThresholds {
+            stuck_eco2_ms: 2 * 60 * 60 * 1000,
+            min_resistance_ohm: 100.0,
+            max_resistance_ohm: 1_000_000_000.0,
+            max_i2c_error_percent: 5,
+            min_firmware: None,
+        }
+    }
+}
+
+/// result of one health check, forward it to your telemetry
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct HealthReport {
+    /// worst severity of all findings below
+    pub severity: Severity,
+    /// part id read from the sensor, None if it could not be read
+    pub part_id: Option<u16>,
+    /// firmware version known to the monitor, see HealthMonitor::set_firmware()
+    pub firmware: Option<FirmwareVersion>,
+    /// firmware is older than HealthThresholds::min_firmware
+    pub firmware_outdated: bool,
+    /// error bit of the status register is set
+    pub status_error: bool,
+    /// eCO2 value has not changed for at least HealthThresholds::stuck_eco2_ms
+    pub eco2_stuck: bool,
+    /// how long (in ms) eCO2 has been unchanged
+    pub eco2_unchanged_ms: u64,
+    /// a hot plate resistance is outside the plausible range
+    pub resistance_out_of_range: bool,
+    /// I2C transactions recorded by the monitor, see HealthMonitor::record()
+    pub i2c_transactions: u32,
+    /// failed I2C transactions recorded by the monitor
+    pub i2c_errors: u32,
+    /// I2C error rate in percent of the recorded transactions
+    pub i2c_error_percent: u8,
+}
+
+/// Keeps the history needed for health checks (stuck values, I2C error rate).
+/// Call Ens160::check_health() periodically, e.g. once a minute.
+/// The monitor only sees the reads made by check_health() itself, unless the results of
+/// normal driver calls are passed to record() as well, so on its own the I2C error rate
+/// is that of the health check reads and not of all traffic to the sensor.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HealthMonitor {
+    thresholds: HealthThresholds,
+    firmware: Option<FirmwareVersion>,
+    last_eco2: Option<u16>,
+    eco2_changed_ms: u64,
+    i2c_transactions: u32,
+    i2c_errors: u32,
+}
+
+impl HealthMonitor {
+    /// create a new monitor with the given thresholds
+    pub fn new(thresholds: HealthThresholds) -> Self {
+        HealthMonitor {
+            thresholds,
+            ..Default::default()
+        }
+    }
+
+    /// the thresholds in use
+    pub fn thresholds(&self) -> &HealthThresholds {
+        &self.thresholds
+    }
+
+    /// the firmware version can only be read in idle mode, so tell the monitor
+    /// the version read during initialization
+    pub fn set_firmware(&mut self, firmware: FirmwareVersion) {
+        self.firmware = Some(firmware);
+    }
+
+    /// record the outcome of a driver call so it counts towards the I2C error rate,
+    /// e.g. `let measurements = ens160.get_measurements(); monitor.record(&measurements);`
+    pub fn record<T, E>(&mut self, result: &Result<T, Error<E>>) {
+        self.i2c_transactions = self.i2c_transactions.saturating_add(1);
+        if let Err(Error::I2c { .. }) = result {
+            self.i2c_errors = self.i2c_errors.saturating_add(1);
+        }
+    }
+
+    /// clear the I2C error statistics, e.g. after they have been reported
+    pub fn reset_i2c_statistics(&mut self) {
+        self.i2c_transactions = 0;
+        self.i2c_errors = 0;
+    }
+
+    /// evaluate the readings of one health check, now_ms is a monotonic millisecond time
+    /// (see sample::Clock).  None means the value could not be read.
+    pub fn evaluate(
+        &mut self,
+        now_ms: u64,
+        part_id: Option<u16>,
+        status_error: Option<bool>,
+        eco2: Option<u16>,
+        resistances_ohm: Option<&[f32]>,
+    ) -> HealthReport {
+        let mut report = HealthReport {
+            part_id,
+            firmware: self.firmware,
+            i2c_transactions: self.i2c_transactions,
+            i2c_errors: self.i2c_errors,
+            ..Default::default()
+        };
+        let mut severity = Severity::Ok;
+
+        if part_id.and_then(Variant::from_part_id).is_none() {
+            severity = severity.max(Severity::Critical);
+        }
+
+        if let (Some(firmware), Some(minimum)) = (self.firmware, self.thresholds.min_firmware) {
+            if firmware < minimum {
+                report.firmware_outdated = true;
+                severity = severity.max(Severity::Warning);
+            }
+        }
+
+        match status_error {
+            Some(true) => {
+                report.status_error = true;
+                severity = severity.max(Severity::Warning);
+            }
+            Some(false) => {}
+            None => severity = severity.max(Severity::Critical),
+        }
+
+        if let Some(eco2) = eco2 {
+            if self.last_eco2 != Some(eco2) {
+                self.last_eco2 = Some(eco2);
+                self.eco2_changed_ms = now_ms;
+            }
+            report.eco2_unchanged_ms = now_ms.saturating_sub(self.eco2_changed_ms);
+            if report.eco2_unchanged_ms >= self.thresholds.stuck_eco2_ms as u64 {
+                report.eco2_stuck = true;
+                severity = severity.max(Severity::Warning);
+            }
+        }
+
+        if let Some(resistances) = resistances_ohm {
+            report.resistance_out_of_range = resistances.iter().any(|r| {
+                !(self.thresholds.min_resistance_ohm..=self.thresholds.max_resistance_ohm)
+                    .contains(r)
+            });
+            if report.resistance_out_of_range {
+                severity = severity.max(Severity::Warning);
+            }
+        }
+
+        if self.i2c_transactions > 0 {
+            let percent = (self.i2c_errors as u64 * 100) / self.i2c_transactions as u64;
+            report.i2c_error_percent = percent as u8;
+            if report.i2c_error_percent > self.thresholds.max_i2c_error_percent {
+                severity = severity.max(Severity::Warning);
+            }
+        }
+
+        report.severity = severity;
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::I2cOp;
+
+    const ENS160_PART_ID: Option<u16> = Some(0x0160);
+
+    #[test]
+    fn recorded_calls_count_towards_error_rate() {
+        let mut monitor = HealthMonitor::default();
+        for _ in 0..9 {
+            monitor.record::<u16, ()>(&Ok(0));
+        }
+        monitor.record::<u16, ()>(&Err(Error::I2c {
+            op: I2cOp::Read,
+            register: 0x20,
+            source: (),
+        }));
+        // driver detected errors are not bus errors
+        monitor.record::<u16, ()>(&Err(Error::OpModeNotCorrect(0x00)));
+        let report = monitor.evaluate(0, ENS160_PART_ID, Some(false), Some(400), None);
+        assert_eq!(report.i2c_transactions, 11);
+        assert_eq!(report.i2c_errors, 1);
+        assert_eq!(report.i2c_error_percent, 9);
+        assert_eq!(report.severity, Severity::Warning);
+
+        monitor.reset_i2c_statistics();
+        let report = monitor.evaluate(0, ENS160_PART_ID, Some(false), Some(400), None);
+        assert_eq!(report.i2c_transactions, 0);
+        assert_eq!(report.severity, Severity::Ok);
+    }
+
+    #[test]
+    fn stuck_eco2_beyond_u32_ms() {
+        let mut monitor = HealthMonitor::default();
+        let start_ms = u32::MAX as u64 - 1000;
+        let stuck_ms = HealthThresholds::default().stuck_eco2_ms as u64;
+        let report = monitor.evaluate(start_ms, ENS160_PART_ID, Some(false), Some(400), None);
+        assert!(!report.eco2_stuck);
+        let report = monitor.evaluate(
+            start_ms + stuck_ms - 1,
+            ENS160_PART_ID,
+            Some(false),
+            Some(400),
+            None,
+        );
+        assert_eq!(report.eco2_unchanged_ms, stuck_ms - 1);
+        assert!(!report.eco2_stuck);
+        let report = monitor.evaluate(
+            start_ms + stuck_ms,
+            ENS160_PART_ID,
+            Some(false),
+            Some(400),
+            None,
+        );
+        assert!(report.eco2_stuck);
+        assert_eq!(report.severity, Severity::Warning);
+    }
+
+    #[test]
+    fn unreadable_sensor_is_critical() {
+        let mut monitor = HealthMonitor::default();
+        let report = monitor.evaluate(0, None, None, None, None);
+        assert_eq!(report.severity, Severity::Critical);
+    }
+}
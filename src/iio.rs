@@ -0,0 +1,244 @@
+// Linux IIO backend:  reads the ENS160 through the in-kernel ens160 IIO driver (sysfs)
+// for systems where the kernel driver has claimed the device, so /dev/i2c-* can not be used.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::data::{AirQualityIndex, Measurements, ECO2};
+
+/// where the kernel lists its IIO devices
+pub const IIO_SYSFS_ROOT: &str = "/sys/bus/iio/devices";
+
+/// name the kernel ens160 driver gives its IIO device
+pub const IIO_DEVICE_NAME: &str = "ens160";
+
+/// ENS160 bound to the Linux ens160 IIO driver.  The kernel exposes eCO2 and TVOC as
+/// concentration channels (in_concentration_co2_* and in_concentration_voc_*), so
+/// get_measurements() reports air_quality_index as Unavailable, etoh as 0 and
+/// raw_resistance at its default (0.0).
+#[derive(Debug, Clone)]
+pub struct IioEns160 {
+    device_dir: PathBuf,
+}
+
+impl IioEns160 {
+    /// find the ens160 IIO device in /sys/bus/iio/devices
+    pub fn find() -> io::Result<Self> {
+        Self::find_in(IIO_SYSFS_ROOT)
+    }
+
+    /// find the ens160 IIO device in a sysfs style directory (e.g. a fake tree for tests):
+    /// the first iio:deviceN sub directory whose name file reads ens160
+    pub fn find_in<P: AsRef<Path>>(root: P) -> io::Result<Self> {
+        let mut device_dirs: Vec<PathBuf> = fs::read_dir(root.as_ref())?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .collect();
+        device_dirs.sort();
+        for device_dir in device_dirs {
+            if let Ok(name) = fs::read_to_string(device_dir.join("name")) {
+                if name.trim() == IIO_DEVICE_NAME {
+                    return Self::open(device_dir);
+                }
+            }
+        }
+        Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no {} IIO device in {}", IIO_DEVICE_NAME, root.as_ref().display()),
+        ))
+    }
+
+    /// use the IIO device directory, e.g. /sys/bus/iio/devices/iio:device0
+    pub fn open<P: Into<PathBuf>>(device_dir: P) -> io::Result<Self> {
+        let device_dir = device_dir.into();
+        if !device_dir.join("in_concentration_co2_raw").exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} has no eCO2 channel", device_dir.display()),
+            ));
+        }
+        Ok(IioEns160 { device_dir })
+    }
+
+    /// the IIO device directory
+    pub fn device_dir(&self) -> &Path {
+        &self.device_dir
+    }
+
+    /// Gets Equivalent Carbon Dioxide measurement in ppm
+    pub fn get_eco2(&self) -> io::Result<ECO2> {
+        // IIO concentrations are in percent, 1 ppm = 0.0001 %
+        let ppm = self.read_channel("co2", 1.0e4)?;
+        Ok(ECO2::from(ppm))
+    }
+
+    /// Get Total Volitaile organic compounds in ppb
+    pub fn get_tvoc(&self) -> io::Result<u16> {
+        // 1 ppb = 0.0000001 %
+        self.read_channel("voc", 1.0e7)
+    }
+
+    /// get eCO2 and TVOC, the values the kernel driver does not expose are left at defaults
+    pub fn get_measurements(&self) -> io::Result<Measurements> {
+        Ok(Measurements {
+            co2eq_ppm: self.get_eco2()?,
+            tvoc_ppb: self.get_tvoc()?,
+            air_quality_index: AirQualityIndex::Unavailable,
+            etoh: 0,
+            raw_resistance: Default::default(),
+        })
+    }
+
+    // raw * scale is the concentration in percent, unit_per_percent converts it to ppm or ppb.
+    // Without a scale file the raw value is taken as already in ppm or ppb.
+    fn read_channel(&self, modifier: &str, unit_per_percent: f64) -> io::Result<u16> {
+        let raw: f64 = self.read_value(&format!("in_concentration_{}_raw", modifier))?;
+        let value = match self.read_value::<f64>(&format!("in_concentration_{}_scale", modifier)) {
+            Ok(scale) => raw * scale * unit_per_percent,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => raw,
+            Err(err) => return Err(err),
+        };
+        Ok(value.round().clamp(0.0, u16::MAX as f64) as u16)
+    }
+
+    fn read_value<T: core::str::FromStr>(&self, file: &str) -> io::Result<T> {
+        let path = self.device_dir.join(file);
+        let text = fs::read_to_string(&path)?;
+        text.trim().parse().map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: can not parse {:?}", path.display(), text.trim()),
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // sysfs style directory tree under the temp dir, removed on drop
+    struct FakeSysfs {
+        root: PathBuf,
+    }
+
+    impl FakeSysfs {
+        fn new(test: &str) -> Self {
+            let root =
+                std::env::temp_dir().join(format!("ens160-iio-{}-{}", std::process::id(), test));
+            let _ = fs::remove_dir_all(&root);
+            fs::create_dir_all(&root).unwrap();
+            FakeSysfs { root }
+        }
+
+        fn device(&self, device: &str, files: &[(&str, &str)]) -> PathBuf {
+            let device_dir = self.root.join(device);
+            fs::create_dir_all(&device_dir).unwrap();
+            for (file, contents) in files {
+                fs::write(device_dir.join(file), contents).unwrap();
+            }
+            device_dir
+        }
+    }
+
+    impl Drop for FakeSysfs {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.root);
+        }
+    }
+
+    #[test]
+    fn raw_only_channels() {
+        let sysfs = FakeSysfs::new("raw-only");
+        sysfs.device("iio:device0", &[("name", "bmp280\n")]);
+        sysfs.device(
+            "iio:device1",
+            &[
+                ("name", "ens160\n"),
+                ("in_concentration_co2_raw", "812\n"),
+                ("in_concentration_voc_raw", "145\n"),
+            ],
+        );
+        let ens160 = IioEns160::find_in(&sysfs.root).unwrap();
+        assert_eq!(ens160.device_dir(), sysfs.root.join("iio:device1"));
+        let measurements = ens160.get_measurements().unwrap();
+        assert_eq!(measurements.co2eq_ppm.get_value(), 812);
+        assert_eq!(measurements.tvoc_ppb, 145);
+        assert_eq!(measurements.air_quality_index, AirQualityIndex::Unavailable);
+        assert_eq!(measurements.etoh, 0);
+    }
+
+    #[test]
+    fn raw_times_scale() {
+        let sysfs = FakeSysfs::new("scale");
+        let device_dir = sysfs.device(
+            "iio:device0",
+            &[
+                ("name", "ens160\n"),
+                ("in_concentration_co2_raw", "406\n"),
+                ("in_concentration_co2_scale", "0.000200\n"),
+                ("in_concentration_voc_raw", "29\n"),
+                ("in_concentration_voc_scale", "0.0000005\n"),
+            ],
+        );
+        let ens160 = IioEns160::open(device_dir).unwrap();
+        assert_eq!(ens160.get_eco2().unwrap().get_value(), 812);
+        assert_eq!(ens160.get_tvoc().unwrap(), 145);
+    }
+
+    #[test]
+    fn missing_device() {
+        let sysfs = FakeSysfs::new("missing");
+        assert_eq!(
+            IioEns160::find_in(&sysfs.root).unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+        sysfs.device("iio:device0", &[("name", "bmp280\n")]);
+        assert_eq!(
+            IioEns160::find_in(&sysfs.root).unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+        // ens160 device without its concentration channels
+        let device_dir = sysfs.device("iio:device1", &[("name", "ens160\n")]);
+        assert_eq!(
+            IioEns160::open(device_dir).unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+        assert_eq!(
+            IioEns160::find_in(sysfs.root.join("no-such-dir"))
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::NotFound
+        );
+    }
+
+    #[test]
+    fn unparsable_values() {
+        let sysfs = FakeSysfs::new("unparsable");
+        let device_dir = sysfs.device(
+            "iio:device0",
+            &[
+                ("name", "ens160\n"),
+                ("in_concentration_co2_raw", "n/a\n"),
+                ("in_concentration_voc_raw", "145\n"),
+                ("in_concentration_voc_scale", "\n"),
+            ],
+        );
+        let ens160 = IioEns160::open(device_dir).unwrap();
+        let err = ens160.get_eco2().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(
+            err.to_string().ends_with("can not parse \"n/a\""),
+            "{}",
+            err
+        );
+        assert_eq!(
+            ens160.get_tvoc().unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+        assert_eq!(
+            ens160.get_measurements().unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+}
@@ -1,21 +1,71 @@
-#![no_std]
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
 #![allow(dead_code)]
 #![allow(unused_variables)]
 
 pub mod error;
 
-use crate::error::Error;
+use crate::error::{Error, I2cOp};
 
 pub mod data;
-use crate::data::ENS160Command;
 use crate::data::OperationMode;
 
 use data::Measurements;
-use data::{AirQualityIndex, Status, ECO2};
+use data::{AirQualityIndex, Status, ECO2, HOT_PLATES};
 
 pub mod constants;
 
+pub mod config;
+
+pub mod firmware;
+
+pub mod command;
+use crate::command::{ClearGpr, Command, GetAppVersion, Nop};
+use crate::firmware::{FirmwareVersion, Quirks};
+use crate::config::{Attached, DeviceInfo, Ens160Config, Timing, Variant};
+
+pub mod units;
+use crate::units::{CentiCelsius, MilliPercent, RawResistance};
+
+pub mod gas_model;
+
+pub mod baseline;
+
+pub mod health;
+
+pub mod resilient;
+
+pub mod multi;
+
+pub mod fusion;
+
+pub mod sensor;
+
+pub mod sample;
+use crate::sample::{Clock, Sample};
+
+#[cfg(not(feature = "async"))]
+pub mod poll;
+
+#[cfg(all(feature = "ccs811", not(feature = "async")))]
+pub mod ccs811;
+
+#[cfg(all(feature = "eh02", not(feature = "async")))]
+pub mod eh02;
+
+#[cfg(feature = "iio")]
+pub mod iio;
+
+#[cfg(all(feature = "record", not(feature = "async")))]
+pub mod record;
+
+#[cfg(all(feature = "sim", not(feature = "async")))]
+pub mod sim;
+
 use crate::constants::DeviceAddress::{Primary, Secondary};
+use crate::constants::{DeviceAddress, ProbeResponse};
+use crate::constants::{Access, Register};
+use crate::gas_model::{GasModel, GasModelInput};
+use crate::health::{HealthMonitor, HealthReport};
 
 #[allow(unused_imports)]
 use crate::constants::{
@@ -32,7 +82,6 @@ use embedded_hal_async::{i2c::I2c as AsyncI2c, delay::DelayNs as AsyncDelayNs};
 //  use embedded_hal::delay::DelayNs;
 //  use embedded_hal::i2c::{I2c, SevenBitAddress};
 
-use libm::{powf, truncf};
 use log::{debug, info};
 
 /// Default I²C address, ADDR pin low
@@ -50,6 +99,16 @@ pub struct Ens160<I2C, D> {
     /// I²C device address
     address: u8,
     delayer: D,
+    /// firmware version, once read
+    firmware: Option<FirmwareVersion>,
+    /// firmware dependent behaviour
+    quirks: Quirks,
+    /// delays for mode transitions and commands
+    timing: Timing,
+    /// sequence number of the last chip cycle returned as a Sample, 0 before the first
+    sequence: u32,
+    /// clock time of the last chip cycle returned as a Sample
+    sequence_ms: u64,
 }
 
 #[cfg(not(feature = "async"))]
@@ -61,19 +120,25 @@ where
     /// create new ENS160 driver with default I2C address: ADDR pin low
     pub fn new(i2c: I2C, delayer: D) -> Self {
         debug!("new called");
-        Self {
-            i2c,
-            address: Primary.into(),
-            delayer,
-        }
+        Self::new_with_address(i2c, delayer, Primary)
     }
     
     /// create new ENS160 driver with secondary I2C address: ADDR pin high
     pub fn new_secondary_address(i2c: I2C, delayer: D) -> Self {
+        Self::new_with_address(i2c, delayer, Secondary)
+    }
+
+    /// create new ENS160 driver with the given I2C address, see also probe()
+    pub fn new_with_address(i2c: I2C, delayer: D, address: DeviceAddress) -> Self {
         Self {
             i2c,
-            address: Secondary.into(),
+            address: address.into(),
             delayer,
+            firmware: None,
+            quirks: Quirks::default(),
+            timing: Timing::default(),
+            sequence: 0,
+            sequence_ms: 0,
         }
     }
     
@@ -92,19 +157,25 @@ where
     /// create new ENS160 driver with default I2C address: ADDR pin low
     pub fn new(i2c: I2C, delayer: D) -> Self {
         debug!("new called");
-        Self {
-            i2c,
-            address: Primary.into(),
-            delayer,
-        }
+        Self::new_with_address(i2c, delayer, Primary)
     }
     
     /// create new ENS160 driver with secondary I2C address: ADDR pin high
     pub fn new_secondary_address(i2c: I2C, delayer: D) -> Self {
+        Self::new_with_address(i2c, delayer, Secondary)
+    }
+
+    /// create new ENS160 driver with the given I2C address, see also probe()
+    pub fn new_with_address(i2c: I2C, delayer: D, address: DeviceAddress) -> Self {
         Self {
             i2c,
-            address: Secondary.into(),
+            address: address.into(),
             delayer,
+            firmware: None,
+            quirks: Quirks::default(),
+            timing: Timing::default(),
+            sequence: 0,
+            sequence_ms: 0,
         }
     }
     
@@ -130,6 +201,29 @@ where
     D: AsyncDelayNs,
 {
 
+    /// find the ENS160 on the bus:  tries the primary then the secondary I2C address and
+    /// returns a driver bound to the address that answered with an ENS160 (or ENS161) part id.
+    /// On failure the error lists what answered at each address and the bus and delayer
+    /// are given back.
+    pub async fn probe(mut i2c: I2C, delayer: D) -> Result<Self, (Error<E>, I2C, D)> {
+        let mut responses = [ProbeResponse::NoResponse; 2];
+        for (response, address) in responses.iter_mut().zip([Primary, Secondary]) {
+            let mut result_buf = [0u8; 2];
+            if i2c
+                .write_read(address.into(), &[ENS160_PART_ID], &mut result_buf).await
+                .is_ok()
+            {
+                let part_id = u16::from_le_bytes(result_buf);
+                if Variant::from_part_id(part_id).is_some() {
+                    info!("ENS160 found at address {:#04x}", u8::from(address));
+                    return Ok(Self::new_with_address(i2c, delayer, address));
+                }
+                *response = ProbeResponse::PartId(part_id);
+            }
+        }
+        Err((Error::DeviceNotFound(responses[0], responses[1]), i2c, delayer))
+    }
+
     // command_buf is an u8 array that starts with command byte followed by command data byte(s)
     async fn write_command<const N: usize>(
         &mut self,
@@ -138,10 +232,14 @@ where
         // debug!("write_command : {:#?}", command_buf);
         self.i2c
             .write(self.address, &command_buf).await
-            .map_err(Error::I2c)
+            .map_err(|source| Error::I2c {
+                op: I2cOp::Write,
+                register: command_buf[0],
+                source,
+            })
     }
 
-    async fn read_register(
+    async fn read_address(
         &mut self,
         register_address: u8,
         buffer: &mut [u8],
@@ -151,53 +249,179 @@ where
         // let mut result_buffer = [0u8; N];
         self.i2c
             .write_read(self.address, &command_buffer, buffer).await
-            .map_err(Error::I2c)?;
+            .map_err(|source| Error::I2c {
+                op: I2cOp::Read,
+                register: register_address,
+                source,
+            })?;
         Ok(())
     }
 
+    /// read a register, buffer may be shorter than the register size to read only its first bytes.
+    /// For advanced use, the other functions cover the documented features.
+    pub async fn read_register(
+        &mut self,
+        register: Register,
+        buffer: &mut [u8],
+    ) -> Result<(), Error<E>> {
+        if buffer.is_empty() || buffer.len() > register.size() {
+            return Err(Error::InvalidRegisterLength(register.address(), buffer.len()));
+        }
+        self.read_address(register.address(), buffer).await
+    }
+
+    /// write a read/write register, data may be shorter than the register size.
+    /// For advanced use, the other functions cover the documented features.
+    pub async fn write_register(&mut self, register: Register, data: &[u8]) -> Result<(), Error<E>> {
+        if register.access() != Access::ReadWrite {
+            return Err(Error::RegisterReadOnly(register.address()));
+        }
+        if data.is_empty() || data.len() > register.size() {
+            return Err(Error::InvalidRegisterLength(register.address(), data.len()));
+        }
+        let mut command_buf = [0u8; 9];
+        command_buf[0] = register.address();
+        command_buf[1..=data.len()].copy_from_slice(data);
+        self.i2c
+            .write(self.address, &command_buf[..=data.len()]).await
+            .map_err(|source| Error::I2c {
+                op: I2cOp::Write,
+                register: register.address(),
+                source,
+            })
+    }
+
     /// set operating mode:  deep sleep, idle, normal operation or reset
     /// reset puts the ENS160 into initial start mode for an hour and it still will persist
-    /// until 24 hours of continuous power on.  
+    /// until 24 hours of continuous power on.  Returns the mode read back,
+    /// Error::OpModeNotCorrect if OPMODE reads back as an unknown value.
     pub async fn set_operation_mode(
         &mut self,
         mode: OperationMode,
     ) -> Result<OperationMode, Error<E>> {
+        let read_back = self.write_operation_mode(mode).await?;
+        OperationMode::try_from(read_back).map_err(Error::OpModeNotCorrect)
+    }
+
+    // write OPMODE, wait for the mode change and return the raw OPMODE read back
+    async fn write_operation_mode(&mut self, mode: OperationMode) -> Result<u8, Error<E>> {
         debug!("setting ens160 operation mode to {:#?}", mode);
         self.write_command([ENS160_OPMODE, mode as u8]).await?;
-        self.delayer.delay_ms(50).await;
+        self.delayer.delay_ms(self.timing.mode_change_ms).await;
         let mut result_buf: [u8; 1] = [0; 1];
-        self.read_register(ENS160_OPMODE, &mut result_buf).await?;
-        return Ok(OperationMode::from(result_buf[0]));
+        self.read_address(ENS160_OPMODE, &mut result_buf).await?;
+        Ok(result_buf[0])
+    }
+
+    /// delays in use for mode transitions and commands
+    pub fn timing(&self) -> Timing {
+        self.timing
+    }
+
+    /// change the delays for mode transitions and commands, initialize_with() and attach()
+    /// take them from their Ens160Config
+    pub fn set_timing(&mut self, timing: Timing) {
+        self.timing = timing;
     }
 
     /// Returns ENS160 part ID, expect 0x0160
     pub async fn get_part_id(&mut self) -> Result<u16, Error<E>> {
         let mut result_buf = [0; 2];
-        self.read_register(ENS160_PART_ID, &mut result_buf[0..2]).await?;
+        self.read_address(ENS160_PART_ID, &mut result_buf[0..2]).await?;
         //   .map(u16::from_le_bytes) // ENS160 returns little endian data
 
         Ok(u16::from_le_bytes(result_buf))
     }
 
-    /// Gets ENS160 firmware version (this library was tested with 5.4.6)
-    pub async fn get_firmware_version(&mut self) -> Result<(u8, u8, u8), Error<E>> {
-        self.write_command([ENS160_COMMAND, ENS160Command::GetAppVersion as u8]).await?;
+    /// Gets ENS160 firmware version (this library was tested with 5.4.6), idle mode only.
+    /// Also selects the firmware quirks the driver applies, see quirks().
+    pub async fn get_firmware_version(&mut self) -> Result<FirmwareVersion, Error<E>> {
+        let version = self.execute(&GetAppVersion).await?;
+        self.set_firmware(version);
+        Ok(version)
+    }
+
+    /// run a command:  checks the sensor is in idle mode (Error::NotIdle otherwise),
+    /// writes the command's GPR_WRITE parameters and code, waits for completion on the status
+    /// register and decodes the GPR_READ response
+    pub async fn execute<C: Command>(&mut self, command: &C) -> Result<C::Response, Error<E>> {
+        let mut opmode: [u8; 1] = [0; 1];
+        self.read_address(ENS160_OPMODE, &mut opmode).await?;
+        if opmode[0] != OperationMode::Idle as u8 {
+            return Err(Error::NotIdle(opmode[0]));
+        }
+        self.send_command(command).await?;
+        self.delayer.delay_ms(self.command_wait_ms()).await;
+        let mut gpr_read: [u8; 8] = [0; 8];
+        if command.has_response() {
+            if !self.wait_group_data_ready().await? {
+                debug!("no new group data after command {:#04x}, reading it anyway", command.code());
+            }
+            self.read_address(ENS160_GPR_READ, &mut gpr_read).await?;
+        }
+        Ok(command.decode(gpr_read))
+    }
+
+    // write the command's GPR_WRITE parameters and code, without the idle check and waits
+    async fn send_command<C: Command>(&mut self, command: &C) -> Result<(), Error<E>> {
+        if let Some(p) = command.parameters() {
+            self.write_command([ENS160_GPR_WRITE, p[0], p[1], p[2], p[3], p[4], p[5], p[6], p[7]])
+                .await?;
+        }
+        self.write_command([ENS160_COMMAND, command.code()]).await
+    }
+
+    // wait after a command before polling for its completion
+    fn command_wait_ms(&self) -> u32 {
+        self.timing.command_ms.max(self.quirks.command_delay_ms)
+    }
+
+    // poll the status register until new group data is ready (command completed),
+    // false on timing.command_timeout_ms
+    async fn wait_group_data_ready(&mut self) -> Result<bool, Error<E>> {
+        let mut waited_ms = 0;
+        loop {
+            if self.get_status().await?.new_group_data_ready() {
+                return Ok(true);
+            }
+            if waited_ms >= self.timing.command_timeout_ms {
+                return Ok(false);
+            }
+            let interval_ms = self.timing.poll_interval_ms.max(1);
+            self.delayer.delay_ms(interval_ms).await;
+            waited_ms += interval_ms;
+        }
+    }
+
+    // read the GetAppVersion result from the group data registers and pick the quirks
+    async fn read_firmware_version(&mut self) -> Result<FirmwareVersion, Error<E>> {
         let mut result_buf: [u8; 8] = [0; 8];
-        self.read_register(ENS160_GPR_READ, &mut result_buf).await?;
-        Ok((result_buf[4], result_buf[5], result_buf[6]))
+        self.read_address(ENS160_GPR_READ, &mut result_buf).await?;
+        let version = GetAppVersion.decode(result_buf);
+        self.set_firmware(version);
+        Ok(version)
+    }
+
+    fn set_firmware(&mut self, version: FirmwareVersion) {
+        self.firmware = Some(version);
+        self.quirks = version.quirks();
+    }
+
+    /// firmware dependent behaviour in use, chosen when the firmware version is read
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
     }
 
-    /// Clears group data registers
+    /// Clears group data registers (Nop then ClearGpr), idle mode only
     pub async fn clear_command(&mut self) -> Result<(), Error<E>> {
-        self.write_command([ENS160_COMMAND, ENS160Command::Nop as u8]).await?;
-        self.write_command([ENS160_COMMAND, ENS160Command::ClearGPR as u8]).await?;
-        Ok(())
+        self.execute(&Nop).await?;
+        self.execute(&ClearGpr).await
     }
 
     /// Gets Equivalent Carbon Dioxide  measurement from the sensor in ppm, returns ECO2 enum.
     pub async fn get_eco2(&mut self) -> Result<ECO2, Error<E>> {
         let mut result_buf = [0; 2];
-        self.read_register(ENS160_DATA_ECO2, &mut result_buf).await?;
+        self.read_address(ENS160_DATA_ECO2, &mut result_buf).await?;
         // debug!("eco2 u16 = {:#?}", result_buf);
         let eco2 = u16::from_le_bytes(result_buf);
         // debug("eco2 u16 = {:#04x}", eco2);
@@ -207,7 +431,7 @@ where
     /// Get Total Volitaile organic compounds in ppb.  No range for indexing given in data sheet
     pub async fn get_tvoc(&mut self) -> Result<u16, Error<E>> {
         let mut result_buf = [0; 2];
-        self.read_register(ENS160_DATA_TVOC, &mut result_buf).await?;
+        self.read_address(ENS160_DATA_TVOC, &mut result_buf).await?;
         Ok(u16::from_le_bytes(result_buf))
         //.map(u16::from_le_bytes)
     }
@@ -216,33 +440,104 @@ where
     /// The air quality index value is matched to the AirQualityIndex enum (resultant)
     pub async fn get_airquality_index(&mut self) -> Result<AirQualityIndex, Error<E>> {
         let mut result_buf = [0; 1];
-        self.read_register(ENS160_DATA_AQI, &mut result_buf).await?;
+        self.read_address(ENS160_DATA_AQI, &mut result_buf).await?;
         debug!(" read ENS160_DATA_AQI result is {}", result_buf[0]);
         Ok(AirQualityIndex::from(result_buf[0]))
     }
 
-    /// get ethanol concentration in ppb
+    /// get ethanol concentration in ppb (DATA_ETOH, the same register as DATA_TVOC)
     pub async fn get_etoh(&mut self) -> Result<u16, Error<E>> {
+        if !self.quirks.etoh_available {
+            return Err(Error::NotSupportedByFirmware(self.firmware.unwrap_or_default()));
+        }
         let mut result_buf: [u8; 2] = [0; 2];
-        self.read_register(ENS160_DATA_ETOH, &mut result_buf).await?;
+        self.read_address(ENS160_DATA_ETOH, &mut result_buf).await?;
         Ok(u16::from_le_bytes(result_buf))
     }
 
     /// get raw resistance value which can be used for custom calulations, in ohms
     pub async fn get_raw_resistance(&mut self) -> Result<f32, Error<E>> {
-        let mut result_buf: [u8; 2] = [0; 2];
-        self.read_register(ENS160_GRP_READ6, &mut result_buf).await?;
+        let raw = self.get_raw_resistance_raw().await?;
         // convert to ohm, see datasheet section 7
-        let exponent: f32 = u16::from_le_bytes(result_buf) as f32;
-        //debug!("raw resistance before conversion {}", exponent);
-        let resistance = powf(2.0, exponent / 2048.0);
-        Ok(resistance)
+        Ok(raw.into())
+    }
+
+    /// get raw resistance value as the register value, RawResistance::ohms() converts it
+    /// without floating point
+    pub async fn get_raw_resistance_raw(&mut self) -> Result<RawResistance, Error<E>> {
+        let mut result_buf: [u8; 2] = [0; 2];
+        self.read_address(ENS160_GRP_READ6, &mut result_buf).await?;
+        Ok(RawResistance(u16::from_le_bytes(result_buf)))
+    }
+
+    /// get raw resistance of all hot plates in ohms, one value per hot plate
+    /// as read from the group data registers (GPR_READ0 to GPR_READ7)
+    pub async fn get_raw_resistances(&mut self) -> Result<[f32; HOT_PLATES], Error<E>> {
+        let raw = self.get_raw_resistances_raw().await?;
+        Ok(raw.map(f32::from))
+    }
+
+    /// get raw resistance of all hot plates as register values, see get_raw_resistances()
+    pub async fn get_raw_resistances_raw(
+        &mut self,
+    ) -> Result<[RawResistance; HOT_PLATES], Error<E>> {
+        let mut result_buf: [u8; 8] = [0; 8];
+        self.read_address(ENS160_GPR_READ, &mut result_buf).await?;
+        let mut resistances = [RawResistance::default(); HOT_PLATES];
+        for (plate, raw) in result_buf.chunks_exact(2).enumerate() {
+            resistances[plate] = RawResistance(u16::from_le_bytes([raw[0], raw[1]]));
+        }
+        Ok(resistances)
+    }
+
+    /// read hot plate resistances and compensation values and feed them to a custom gas model.
+    /// baseline_ohm overrides the model's own baseline when given (e.g. a restored baseline)
+    pub async fn evaluate_gas_model<M: GasModel>(
+        &mut self,
+        model: &mut M,
+        baseline_ohm: Option<[f32; HOT_PLATES]>,
+    ) -> Result<M::Output, Error<E>> {
+        let resistances_ohm = self.get_raw_resistances().await?;
+        let (temp_c, rh_percent) = self.get_temp_rh_comp().await?;
+        let input = GasModelInput {
+            resistances_ohm,
+            temp_c,
+            rh_percent,
+            baseline_ohm,
+        };
+        Ok(model.update(&input))
+    }
+
+    /// run a health check:  reads part id, status, eCO2 and the hot plate resistances and
+    /// lets the monitor evaluate them.  I2C errors do not fail the check, they are counted
+    /// by the monitor and show up in the report (other driver calls only count when their
+    /// results are passed to HealthMonitor::record()).  now_ms is a monotonic millisecond time.
+    pub async fn check_health(
+        &mut self,
+        monitor: &mut HealthMonitor,
+        now_ms: u64,
+    ) -> HealthReport {
+        let part_id = self.get_part_id().await;
+        monitor.record(&part_id);
+        let status = self.get_status().await;
+        monitor.record(&status);
+        let eco2 = self.get_eco2().await;
+        monitor.record(&eco2);
+        let resistances = self.get_raw_resistances().await;
+        monitor.record(&resistances);
+        monitor.evaluate(
+            now_ms,
+            part_id.ok(),
+            status.ok().map(|status| status.error()),
+            eco2.ok().map(|eco2| eco2.get_value()),
+            resistances.as_ref().ok().map(|r| &r[..]),
+        )
     }
 
     /// get ENS160 status flags
     pub async fn get_status(&mut self) -> Result<Status, Error<E>> {
         let mut result_buf = [0; 1];
-        self.read_register(ENS160_DEVICE_STATUS, &mut result_buf).await?;
+        self.read_address(ENS160_DEVICE_STATUS, &mut result_buf).await?;
         //debug!(" raw ens160 status byte is {:#04x}", result_buf[0]);
         Ok(Status(result_buf[0]))
     }
@@ -250,42 +545,68 @@ where
     /// read ENS160 group data
     pub async fn get_group_data(&mut self) -> Result<[u8; 8], Error<E>> {
         let mut result_buf: [u8; 8] = [0; 8];
-        self.read_register(ENS160_GPR_READ, &mut result_buf).await?;
+        self.read_address(ENS160_GPR_READ, &mut result_buf).await?;
         // debug!(" group register read results are {:#?}", result_buf);
         Ok(result_buf)
     }
     /// set the temperature in degrees C and relative humdity in percent for compensation calculation
-    pub async fn set_temp_rh_comp(
+    pub async fn set_temp_rh_comp(&mut self, temp_c: f32, rh_percent: f32) -> Result<(), Error<E>> {
+        let temp_val: u16 = units::temperature_to_raw(temp_c); // to Kelvin and scale it
+        //info!("setting temp comp to {:#04x}", temp_val.to_le());
+        self.write_temp_rh_comp(temp_val, units::humidity_to_raw(rh_percent))
+            .await
+    }
+
+    /// set the compensation temperature and relative humidity as fixed-point values, writes
+    /// the same registers as set_temp_rh_comp() without floating point
+    pub async fn set_temp_rh_comp_fixed(
         &mut self,
-        temp_c: f32,
-        rh_percent: u16,
+        temperature: CentiCelsius,
+        humidity: MilliPercent,
     ) -> Result<(), Error<E>> {
+        self.write_temp_rh_comp(temperature.to_raw(), humidity.to_raw())
+            .await
+    }
+
+    // write TEMP_IN (Kelvin * 64) and RH_IN (percent * 512)
+    async fn write_temp_rh_comp(&mut self, temp_raw: u16, rh_raw: u16) -> Result<(), Error<E>> {
         let mut buffer: [u8; 2];
-        let temp_val: u16 = truncf((temp_c + 273.15) * 64.0) as u16; // to Kelvin and scale it
-                                                                     //info!("setting temp comp to {:#04x}", temp_val.to_le());
-        buffer = temp_val.to_le_bytes(); // ???? or is it be
+        buffer = temp_raw.to_le_bytes(); // ???? or is it be
         self.write_command([ENS160_TEMP_IN, buffer[0], buffer[1]]).await?;
 
-        buffer = rh_percent.to_le_bytes();
+        buffer = rh_raw.to_le_bytes();
         //debug!("setting rh comp to {:#04x} {:#04x}", buffer[0], buffer[1]);
         self.write_command([ENS160_RH_IN, buffer[0], buffer[1]]).await?;
 
         Ok(())
     }
 
-    pub async fn get_temp_rh_comp(&mut self) -> Result<(f32, u16), Error<E>> {
-        let mut result_buf: [u8; 2] = [0; 2];
-        self.read_register(ENS160_DATA_T, &mut result_buf).await?;
-        let value: u16 = u16::from_le_bytes(result_buf);
-        let temp_comp_c = ((value as f32) / 64.0) - 273.15;
+    pub async fn get_temp_rh_comp(&mut self) -> Result<(f32, f32), Error<E>> {
+        let (temp_raw, rh_raw) = self.read_temp_rh_comp().await?;
+        let temp_comp_c = units::temperature_from_raw(temp_raw);
         //debug!("temp c compensation is {}", temp_comp_c);
-
-        self.read_register(ENS160_DATA_RH, &mut result_buf).await?;
-        let rh: u16 = u16::from_le_bytes(result_buf);
+        let rh = units::humidity_from_raw(rh_raw);
         //debug!("read rh back as {}", rh);
         Ok((temp_comp_c, rh))
     }
 
+    /// get the compensation temperature and relative humidity as fixed-point values
+    pub async fn get_temp_rh_comp_fixed(
+        &mut self,
+    ) -> Result<(CentiCelsius, MilliPercent), Error<E>> {
+        let (temp_raw, rh_raw) = self.read_temp_rh_comp().await?;
+        Ok((CentiCelsius::from_raw(temp_raw), MilliPercent::from_raw(rh_raw)))
+    }
+
+    // read DATA_T (Kelvin * 64) and DATA_RH (percent * 512)
+    async fn read_temp_rh_comp(&mut self) -> Result<(u16, u16), Error<E>> {
+        let mut result_buf: [u8; 2] = [0; 2];
+        self.read_address(ENS160_DATA_T, &mut result_buf).await?;
+        let temp_raw = u16::from_le_bytes(result_buf);
+        self.read_address(ENS160_DATA_RH, &mut result_buf).await?;
+        Ok((temp_raw, u16::from_le_bytes(result_buf)))
+    }
+
     /// configure the interrupt pin of ENS160.  See data sheet for config:u8 parameter
     /// or use the handy InterruptPinConfig::builder() and its function to generate the
     /// config:u8 parameter for you.
@@ -293,50 +614,137 @@ where
     pub async fn config_interrupt_pin(&mut self, config: u8) -> Result<u8, Error<E>> {
         self.write_command([ENS160_CONFIG, config]).await?;
         let mut result_buf: [u8; 1] = [0; 1];
-        self.read_register(ENS160_CONFIG, &mut result_buf).await?;
+        self.read_address(ENS160_CONFIG, &mut result_buf).await?;
         Ok(result_buf[0])
     }
 
-    /// initialize the ENS160 device
+    /// initialize the ENS160 device with the default configuration (Standard mode) and the
+    /// current timing (see set_timing()), returns true on success.  See initialize_with() for
+    /// more control.
     pub async fn initialize(&mut self) -> Result<bool, Error<E>> {
+        let config = Ens160Config {
+            timing: self.timing,
+            ..Default::default()
+        };
+        self.initialize_with(&config).await?;
+        Ok(true)
+    }
+
+    /// initialize the ENS160 device:  idle mode, part id check, clear GPR, firmware check,
+    /// interrupt pin and compensation setup then the configured operating mode.
+    /// Returns the device part id, variant and firmware version.
+    pub async fn initialize_with(&mut self, config: &Ens160Config) -> Result<DeviceInfo, Error<E>> {
         //self.reset()?;  NO, this will put ENS160 back to factory defaults including InitialStartUp 24 hours
-        // self.set_operation_mode(OperationMode::Reset)?;
-        //self.delayer.delay_ms(250);
-        self.set_operation_mode(OperationMode::Idle).await?;
-        //self.idle_mode()?;
+        self.timing = config.timing;
+        let idle = self.write_operation_mode(OperationMode::Idle).await?;
+        if idle != OperationMode::Idle as u8 {
+            return Err(Error::OpModeNotCorrect(idle));
+        }
+        let the_status = self.get_status().await?;
+        debug!(" command to idle, ENS160 status is {:#?}", the_status);
+        let part_id = self.get_part_id().await?;
+        let variant = Variant::from_part_id(part_id).ok_or(Error::UnexpectedChipId(part_id))?;
+        info!("ENS160 part id is good {:#06x}", part_id);
+        self.delayer.delay_ms(config.timing.idle_ms).await;
+
+        self.clear_command().await?;
         let the_status = self.get_status().await?;
-        debug!(
-            " command to idle, ENS160 status is {:#?}", the_status );
-        if let Ok(part_id) = self.get_part_id().await {
-            if part_id != 0x0160 {
-                Err(Error::UnexpectedChipId(part_id as u16))
-            } else {
-                info!("ENS160 part id is good {}", part_id);
-                self.delayer.delay_ms(50).await;
-                self.clear_command().await?;
-                let the_status = self.get_status().await?;
-                debug!(" command to clear grp data, ENS160 status is {:#?}", the_status );
-                self.delayer.delay_ms(50).await;
-                let (fw_major, fw_minor, fw_build) = self.get_firmware_version().await?;
-                info!("firmware version {}.{}.{}", fw_major, fw_minor, fw_build);
-                self.delayer.delay_ms(10).await;
-                // self.standard_mode()?;
-                let new_mode = self.set_operation_mode(OperationMode::Standard).await?;
-                if new_mode != OperationMode::Standard {
-                    return Err(Error::OpModeNotCorrect(new_mode as u8));
+        debug!(" command to clear grp data, ENS160 status is {:#?}", the_status);
+        self.delayer.delay_ms(config.timing.clear_gpr_ms).await;
+
+        let firmware = self.get_firmware_version().await?;
+        info!("firmware version {}", firmware);
+        if let Some(minimum) = config.min_firmware {
+            if firmware < minimum {
+                return Err(Error::UnsupportedFirmware(firmware));
+            }
+        }
+        self.delayer.delay_ms(config.timing.firmware_ms).await;
+
+        if let Some(int_config) = config.interrupt_config {
+            let read_back = self.config_interrupt_pin(int_config).await?;
+            if read_back != int_config {
+                return Err(Error::InterruptConfigNotCorrect(read_back));
+            }
+        }
+        if let Some((temp_c, rh_percent)) = config.compensation {
+            self.set_temp_rh_comp(temp_c, rh_percent).await?;
+        }
+
+        if config.operation_mode != OperationMode::Idle {
+            let new_mode = self.write_operation_mode(config.operation_mode).await?;
+            if new_mode != config.operation_mode as u8 {
+                return Err(Error::OpModeNotCorrect(new_mode));
+            }
+            self.delayer.delay_ms(config.timing.standard_ms).await;
+            let the_status = self.get_status().await?;
+            debug!(
+                " command to {:#?} mode, ENS160 status is {:#?}",
+                config.operation_mode, the_status
+            );
+        }
+        Ok(DeviceInfo {
+            part_id,
+            variant,
+            firmware: Some(firmware),
+        })
+    }
+
+    /// attach to an ENS160 that may still be running (e.g. after a host MCU watchdog reset)
+    /// without restarting its warm-up.  If the sensor is already in the configured mode
+    /// without error, only the interrupt pin config and compensation values that differ
+    /// are written.  Otherwise it falls back to initialize_with().
+    pub async fn attach(&mut self, config: &Ens160Config) -> Result<Attached, Error<E>> {
+        self.timing = config.timing;
+        let part_id = self.get_part_id().await?;
+        let variant = Variant::from_part_id(part_id).ok_or(Error::UnexpectedChipId(part_id))?;
+        let mut result_buf: [u8; 1] = [0; 1];
+        self.read_address(ENS160_OPMODE, &mut result_buf).await?;
+        let status = self.get_status().await?;
+        debug!("attach: opmode {:#04x}, status {:#?}", result_buf[0], status);
+
+        if result_buf[0] != config.operation_mode as u8 || status.error() {
+            info!("ENS160 not running as configured, initializing");
+            let device = self.initialize_with(config).await?;
+            let status = self.get_status().await?;
+            return Ok(Attached {
+                device,
+                reinitialized: true,
+                validity: status.validity_flag(),
+            });
+        }
+
+        if let Some(int_config) = config.interrupt_config {
+            self.read_address(ENS160_CONFIG, &mut result_buf).await?;
+            if result_buf[0] != int_config {
+                let read_back = self.config_interrupt_pin(int_config).await?;
+                if read_back != int_config {
+                    return Err(Error::InterruptConfigNotCorrect(read_back));
                 }
-                self.delayer.delay_ms(150).await;
-                let the_status = self.get_status().await?;
-                debug!(" command to std mode, ENS160 status is {:#?}", the_status );
-                // read opmode register
-                let mut result_buf: [u8; 1] = [0; 1];
-                self.read_register(ENS160_OPMODE, &mut result_buf).await?;
-                debug!("opmode read is {:#04x}", result_buf[0]);
-                Ok(true)
             }
-        } else {
-            Ok(false)
         }
+        if let Some((temp_c, rh_percent)) = config.compensation {
+            let mut comp_buf: [u8; 2] = [0; 2];
+            self.read_address(ENS160_DATA_T, &mut comp_buf).await?;
+            let temp_raw = u16::from_le_bytes(comp_buf);
+            self.read_address(ENS160_DATA_RH, &mut comp_buf).await?;
+            let rh_raw = u16::from_le_bytes(comp_buf);
+            if temp_raw != units::temperature_to_raw(temp_c)
+                || rh_raw != units::humidity_to_raw(rh_percent)
+            {
+                self.set_temp_rh_comp(temp_c, rh_percent).await?;
+            }
+        }
+        info!("attached to running ENS160, validity {:#?}", status.validity_flag());
+        Ok(Attached {
+            device: DeviceInfo {
+                part_id,
+                variant,
+                firmware: None,
+            },
+            reinitialized: false,
+            validity: status.validity_flag(),
+        })
     }
 
     /// get all measurements from sensor
@@ -344,7 +752,11 @@ where
         let eco2 = self.get_eco2().await?;
         let tvoc = self.get_tvoc().await?;
         let aqi = self.get_airquality_index().await?;
-        let etoh = self.get_etoh().await?;
+        let etoh = if self.quirks.etoh_available {
+            self.get_etoh().await?
+        } else {
+            0
+        };
         let raw_resistance = self.get_raw_resistance().await?;
         let measurements: Measurements = Measurements {
             co2eq_ppm: eco2,
@@ -356,5 +768,265 @@ where
         Ok(measurements)
     }
 
+    /// measurements of a new chip cycle with timestamp and sequence number, None if the
+    /// cycle was already returned.  Reading the data registers by other calls
+    /// (get_measurements(), get_eco2(), ...) also uses up the cycle.
+    pub async fn read_sample<C: Clock>(&mut self, clock: &mut C) -> Result<Option<Sample>, Error<E>> {
+        if !self.get_status().await?.new_data_ready() {
+            return Ok(None);
+        }
+        let measurements = self.get_measurements().await?;
+        Ok(Some(self.next_sample(measurements, clock.now_ms())))
+    }
+
+    /// measurements of the latest chip cycle, a repeated cycle keeps its sequence number and
+    /// timestamp, age_ms tells how long ago it was first read
+    pub async fn read_latest_sample<C: Clock>(&mut self, clock: &mut C) -> Result<Sample, Error<E>> {
+        let new_cycle = self.get_status().await?.new_data_ready() || self.sequence == 0;
+        let measurements = self.get_measurements().await?;
+        let now_ms = clock.now_ms();
+        if new_cycle {
+            return Ok(self.next_sample(measurements, now_ms));
+        }
+        Ok(Sample {
+            measurements,
+            timestamp_ms: self.sequence_ms,
+            sequence: self.sequence,
+            age_ms: now_ms.saturating_sub(self.sequence_ms),
+        })
+    }
+
+    // count a new chip cycle
+    fn next_sample(&mut self, measurements: Measurements, now_ms: u64) -> Sample {
+        self.sequence = self.sequence.wrapping_add(1).max(1);
+        self.sequence_ms = now_ms;
+        Sample {
+            measurements,
+            timestamp_ms: now_ms,
+            sequence: self.sequence,
+            age_ms: 0,
+        }
+    }
+
     // Interrupt pin configuration
 }
+
+#[cfg(all(test, feature = "sim", not(feature = "async")))]
+mod tests {
+    use core::cell::RefCell;
+
+    use super::*;
+    use crate::data::ValidityFlag;
+    use crate::sim::{Ens160Sim, SimConfig, SimDelay, SimError, Tamper, CYCLE_MS};
+
+    type TamperEns160<'s, 'e> = Ens160<Tamper<'s, 'e>, SimDelay<'s, 'e>>;
+
+    fn driver<'s, 'e>(
+        sim: &'s RefCell<Ens160Sim<'e>>,
+        on_read: fn(u8, &mut [u8]),
+    ) -> TamperEns160<'s, 'e> {
+        Ens160::new(Tamper::new(sim, on_read), SimDelay::new(sim))
+    }
+
+    fn untouched(_register: u8, _buffer: &mut [u8]) {}
+
+    fn register(ens160: &mut TamperEns160, address: u8) -> u8 {
+        let mut buffer = [0u8; 1];
+        ens160.read_address(address, &mut buffer).unwrap();
+        buffer[0]
+    }
+
+    fn config() -> Ens160Config {
+        Ens160Config::builder()
+            .interrupt_config(0x23)
+            .compensation(21.0, 40.0)
+            .build()
+    }
+
+    #[test]
+    fn initialize_with_config() {
+        let sim = RefCell::new(Ens160Sim::new(SimConfig::default(), &[]));
+        let mut ens160 = driver(&sim, untouched);
+        let info = ens160.initialize_with(&config()).unwrap();
+        assert_eq!(
+            info,
+            DeviceInfo {
+                part_id: 0x0160,
+                variant: Variant::Ens160,
+                firmware: Some(FirmwareVersion::TESTED),
+            }
+        );
+        assert_eq!(
+            register(&mut ens160, ENS160_OPMODE),
+            OperationMode::Standard as u8
+        );
+        assert_eq!(register(&mut ens160, ENS160_CONFIG), 0x23);
+        let (_, rh_percent) = ens160.get_temp_rh_comp().unwrap();
+        assert_eq!(rh_percent, 40.0);
+        assert_eq!(
+            ens160.get_status().unwrap().validity_flag(),
+            ValidityFlag::WarmupPhase
+        );
+    }
+
+    #[test]
+    fn initialize_with_min_firmware() {
+        let sim = RefCell::new(Ens160Sim::new(SimConfig::default(), &[]));
+        let mut ens160 = driver(&sim, untouched);
+        let accepted = config().min_firmware(FirmwareVersion::TESTED);
+        assert!(ens160.initialize_with(&accepted).is_ok());
+        let too_new = config().min_firmware(FirmwareVersion::new(5, 4, 7));
+        assert!(matches!(
+            ens160.initialize_with(&too_new),
+            Err(Error::UnsupportedFirmware(FirmwareVersion::TESTED))
+        ));
+        // the sensor stays idle, nothing after the firmware check was written
+        assert_eq!(
+            register(&mut ens160, ENS160_OPMODE),
+            OperationMode::Idle as u8
+        );
+    }
+
+    #[test]
+    fn initialize_with_interrupt_config_mismatch() {
+        fn stuck_config(register: u8, buffer: &mut [u8]) {
+            if register == ENS160_CONFIG {
+                buffer[0] = 0x00;
+            }
+        }
+        let sim = RefCell::new(Ens160Sim::new(SimConfig::default(), &[]));
+        let mut ens160 = driver(&sim, stuck_config);
+        let result: Result<DeviceInfo, Error<SimError>> = ens160.initialize_with(&config());
+        assert!(matches!(
+            result,
+            Err(Error::InterruptConfigNotCorrect(0x00))
+        ));
+        assert_eq!(
+            register(&mut ens160, ENS160_OPMODE),
+            OperationMode::Idle as u8
+        );
+    }
+
+    // a sensor initialized with config() and out of warm-up, then the host MCU restarts
+    fn running_sensor(sim: &RefCell<Ens160Sim>) {
+        let mut ens160 = driver(sim, untouched);
+        ens160.initialize_with(&config()).unwrap();
+        sim.borrow_mut().advance(crate::sim::WARMUP_MS + 1000);
+    }
+
+    #[test]
+    fn attach_to_running_sensor() {
+        let sim = RefCell::new(Ens160Sim::new(SimConfig::default(), &[]));
+        running_sensor(&sim);
+        let mut ens160 = driver(&sim, untouched);
+        let attached = ens160.attach(&config()).unwrap();
+        assert!(!attached.reinitialized);
+        assert_eq!(attached.validity, ValidityFlag::NormalOperation);
+        assert_eq!(attached.device.part_id, 0x0160);
+        assert_eq!(attached.device.firmware, None);
+        // matching CONFIG and compensation are not written, the warm-up is not restarted
+        assert!(ens160.i2c.written.is_empty());
+        assert_eq!(
+            ens160.get_status().unwrap().validity_flag(),
+            ValidityFlag::NormalOperation
+        );
+    }
+
+    #[test]
+    fn attach_writes_only_what_differs() {
+        let sim = RefCell::new(Ens160Sim::new(SimConfig::default(), &[]));
+        running_sensor(&sim);
+        let mut ens160 = driver(&sim, untouched);
+        let changed = config().interrupt_config(0x21);
+        let attached = ens160.attach(&changed).unwrap();
+        assert!(!attached.reinitialized);
+        assert_eq!(ens160.i2c.written, [ENS160_CONFIG]);
+        assert_eq!(register(&mut ens160, ENS160_CONFIG), 0x21);
+
+        ens160.i2c.written.clear();
+        let changed = changed.compensation(21.0, 45.5);
+        assert!(!ens160.attach(&changed).unwrap().reinitialized);
+        assert_eq!(ens160.i2c.written, [ENS160_TEMP_IN, ENS160_RH_IN]);
+        let (_, rh_percent) = ens160.get_temp_rh_comp().unwrap();
+        assert_eq!(rh_percent, 45.5);
+    }
+
+    #[test]
+    fn attach_initializes_sensor_in_other_mode() {
+        let sim = RefCell::new(Ens160Sim::new(SimConfig::default(), &[]));
+        let mut ens160 = driver(&sim, untouched);
+        let idle = config().operation_mode(OperationMode::Idle);
+        ens160.initialize_with(&idle).unwrap();
+        sim.borrow_mut().advance(crate::sim::WARMUP_MS);
+
+        let mut ens160 = driver(&sim, untouched);
+        let attached = ens160.attach(&config()).unwrap();
+        assert!(attached.reinitialized);
+        assert_eq!(attached.device.firmware, Some(FirmwareVersion::TESTED));
+        assert_eq!(attached.validity, ValidityFlag::WarmupPhase);
+        assert_eq!(
+            register(&mut ens160, ENS160_OPMODE),
+            OperationMode::Standard as u8
+        );
+    }
+
+    #[test]
+    fn attach_initializes_sensor_with_error() {
+        fn error_bit(register: u8, buffer: &mut [u8]) {
+            if register == ENS160_DEVICE_STATUS {
+                buffer[0] |= 0x40;
+            }
+        }
+        let sim = RefCell::new(Ens160Sim::new(SimConfig::default(), &[]));
+        running_sensor(&sim);
+        let mut ens160 = driver(&sim, error_bit);
+        let attached = ens160.attach(&config()).unwrap();
+        assert!(attached.reinitialized);
+        assert!(ens160.i2c.written.contains(&ENS160_OPMODE));
+    }
+
+    #[test]
+    fn read_sample_once_per_cycle() {
+        let sim = RefCell::new(Ens160Sim::new(SimConfig::default(), &[]));
+        let mut ens160 = driver(&sim, untouched);
+        ens160.initialize().unwrap();
+        let mut clock = || sim.borrow().time_ms();
+        sim.borrow_mut().advance(CYCLE_MS);
+        let first = ens160.read_sample(&mut clock).unwrap().unwrap();
+        assert_eq!((first.sequence, first.age_ms), (1, 0));
+        assert_eq!(first.timestamp_ms, sim.borrow().time_ms());
+        // the same chip cycle is only returned once
+        sim.borrow_mut().advance(100);
+        assert_eq!(ens160.read_sample(&mut clock).unwrap(), None);
+
+        sim.borrow_mut().advance(CYCLE_MS);
+        let second = ens160.read_sample(&mut clock).unwrap().unwrap();
+        assert_eq!((second.sequence, second.age_ms), (2, 0));
+        assert_eq!(second.timestamp_ms, first.timestamp_ms + 100 + CYCLE_MS);
+    }
+
+    #[test]
+    fn read_latest_sample_repeats_cycle() {
+        let sim = RefCell::new(Ens160Sim::new(SimConfig::default(), &[]));
+        let mut ens160 = driver(&sim, untouched);
+        ens160.initialize().unwrap();
+        let mut clock = || sim.borrow().time_ms();
+        sim.borrow_mut().advance(CYCLE_MS);
+        let latest = ens160.read_latest_sample(&mut clock).unwrap();
+        assert_eq!((latest.sequence, latest.age_ms), (1, 0));
+        for age_ms in [300, 600] {
+            sim.borrow_mut().advance(300);
+            let repeated = ens160.read_latest_sample(&mut clock).unwrap();
+            assert_eq!(repeated.sequence, 1);
+            assert_eq!(repeated.timestamp_ms, latest.timestamp_ms);
+            assert_eq!(repeated.age_ms, age_ms);
+            assert_eq!(repeated.age_at(clock()), age_ms);
+        }
+        assert_eq!(ens160.read_sample(&mut clock).unwrap(), None);
+
+        sim.borrow_mut().advance(CYCLE_MS);
+        let next = ens160.read_latest_sample(&mut clock).unwrap();
+        assert_eq!((next.sequence, next.age_ms), (2, 0));
+        assert_eq!(next.timestamp_ms, clock());
+    }
+}
@@ -0,0 +1,323 @@
+// several ENS160 devices on one I2C bus, including behind a TCA9548A I2C mux
+
+use core::cell::RefCell;
+
+use crate::config::{DeviceInfo, Ens160Config};
+use crate::constants::DeviceAddress;
+use crate::data::Measurements;
+use crate::error::Error;
+use crate::Ens160;
+
+#[cfg(not(feature = "async"))]
+use embedded_hal::{
+    delay::DelayNs,
+    i2c::{ErrorType, I2c, Operation},
+};
+#[cfg(feature = "async")]
+use embedded_hal_async::{
+    delay::DelayNs as AsyncDelayNs,
+    i2c::{ErrorType, I2c as AsyncI2c, Operation},
+};
+
+/// TCA9548A I2C address with A0, A1 and A2 low
+pub const TCA9548A_DEFAULT_ADDRESS: u8 = 0x70;
+/// number of TCA9548A channels, numbered 0 to 7
+pub const TCA9548A_CHANNELS: u8 = 8;
+
+/// One channel of a TCA9548A I2C mux on a shared bus.  The mux is switched to this channel
+/// before every transaction, so drivers on different channels can share the bus.
+/// With async the bus stays borrowed across awaits:  only use the channels of one bus
+/// from one task at a time.
+pub struct MuxChannel<'a, I2C> {
+    bus: &'a RefCell<I2C>,
+    mux_address: u8,
+    channel: u8,
+}
+
+impl<'a, I2C> MuxChannel<'a, I2C> {
+    /// channel (0 to 7) of the mux at mux_address on bus, None for channels above 7
+    pub fn new(bus: &'a RefCell<I2C>, mux_address: u8, channel: u8) -> Option<Self> {
+        if channel >= TCA9548A_CHANNELS {
+            return None;
+        }
+        Some(MuxChannel {
+            bus,
+            mux_address,
+            channel,
+        })
+    }
+
+    /// mux channel number
+    pub fn channel(&self) -> u8 {
+        self.channel
+    }
+}
+
+impl<I2C: ErrorType> ErrorType for MuxChannel<'_, I2C> {
+    type Error = I2C::Error;
+}
+
+#[cfg(not(feature = "async"))]
+impl<I2C: I2c> I2c for MuxChannel<'_, I2C> {
+    fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        let mut bus = self.bus.borrow_mut();
+        bus.write(self.mux_address, &[1 << self.channel])?;
+        bus.transaction(address, operations)
+    }
+}
+
+#[cfg(feature = "async")]
+impl<I2C: AsyncI2c> AsyncI2c for MuxChannel<'_, I2C> {
+    // the mux channel must stay selected for the whole transaction, see MuxChannel docs
+    #[allow(clippy::await_holding_refcell_ref)]
+    async fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        let mut bus = self.bus.borrow_mut();
+        bus.write(self.mux_address, &[1 << self.channel]).await?;
+        bus.transaction(address, operations).await
+    }
+}
+
+/// where a sensor is:  mux channel and device address on that channel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SensorId {
+    /// TCA9548A channel (0 to 7)
+    pub channel: u8,
+    /// ENS160 I2C address on that channel
+    pub address: DeviceAddress,
+}
+
+/// result of one sensor of an Ens160Array
+pub type SensorResult<T, E> = (SensorId, Result<T, Error<E>>);
+
+/// Manager for N ENS160 devices behind a TCA9548A mux, all on one I2C bus.
+/// Each sensor is an Ens160 driver bound to its mux channel and address.
+pub struct Ens160Array<'a, I2C, D, const N: usize> {
+    sensors: [(SensorId, Ens160<MuxChannel<'a, I2C>, D>); N],
+}
+
+#[maybe_async_cfg::maybe(
+    sync(
+        cfg(not(feature = "async")),
+        self = "Ens160Array",
+        idents(AsyncI2c(sync = "I2c"), AsyncDelayNs(sync = "DelayNs"))
+    ),
+    async(feature = "async", keep_self)
+)]
+impl<'a, I2C, D, E, const N: usize> Ens160Array<'a, I2C, D, N>
+where
+    I2C: AsyncI2c<Error = E>,
+    D: AsyncDelayNs + Clone,
+{
+    /// create the drivers for the sensors at ids, behind the mux at mux_address on bus.
+    /// None if an id has a channel above 7.
+    pub fn new(
+        bus: &'a RefCell<I2C>,
+        mux_address: u8,
+        ids: [SensorId; N],
+        delayer: D,
+    ) -> Option<Self> {
+        if ids.iter().any(|id| id.channel >= TCA9548A_CHANNELS) {
+            return None;
+        }
+        Some(Ens160Array {
+            sensors: ids.map(|id| {
+                let channel = MuxChannel {
+                    bus,
+                    mux_address,
+                    channel: id.channel,
+                };
+                (id, Ens160::new_with_address(channel, delayer.clone(), id.address))
+            }),
+        })
+    }
+
+    /// ids of the managed sensors
+    pub fn ids(&self) -> [SensorId; N] {
+        self.sensors.each_ref().map(|(id, _)| *id)
+    }
+
+    /// driver of one sensor, for calls not covered by the manager
+    pub fn sensor_mut(&mut self, id: SensorId) -> Option<&mut Ens160<MuxChannel<'a, I2C>, D>> {
+        self.sensors
+            .iter_mut()
+            .find(|(sensor_id, _)| *sensor_id == id)
+            .map(|(_, sensor)| sensor)
+    }
+
+    /// initialize all sensors with the same configuration, one result per sensor
+    pub async fn initialize_all(
+        &mut self,
+        config: &Ens160Config,
+    ) -> [SensorResult<DeviceInfo, E>; N] {
+        let mut results: [Option<SensorResult<DeviceInfo, E>>; N] = core::array::from_fn(|_| None);
+        for (result, (id, sensor)) in results.iter_mut().zip(self.sensors.iter_mut()) {
+            *result = Some((*id, sensor.initialize_with(config).await));
+        }
+        results.map(Option::unwrap)
+    }
+
+    /// get the measurements of all sensors, one result per sensor
+    pub async fn sample_all(&mut self) -> [SensorResult<Measurements, E>; N] {
+        let mut results: [Option<SensorResult<Measurements, E>>; N] =
+            core::array::from_fn(|_| None);
+        for (result, (id, sensor)) in results.iter_mut().zip(self.sensors.iter_mut()) {
+            *result = Some((*id, sensor.get_measurements().await));
+        }
+        results.map(Option::unwrap)
+    }
+}
+
+#[cfg(all(test, feature = "sim", not(feature = "async")))]
+mod tests {
+    use embedded_hal::i2c::{ErrorKind, NoAcknowledgeSource};
+
+    use super::*;
+    use crate::sim::{Ens160Sim, SimConfig, SimError, SimI2c};
+
+    // a TCA9548A with a simulated ENS160 on some channels, logging every transaction as
+    // (address, bytes written)
+    struct MuxBus<'s, 'e> {
+        channels: [Option<SimI2c<'s, 'e>>; 8],
+        selected: u8,
+        log: Vec<(u8, Vec<u8>)>,
+    }
+
+    impl ErrorType for MuxBus<'_, '_> {
+        type Error = SimError;
+    }
+
+    impl I2c for MuxBus<'_, '_> {
+        fn transaction(
+            &mut self,
+            address: u8,
+            operations: &mut [Operation<'_>],
+        ) -> Result<(), SimError> {
+            let mut written = Vec::new();
+            for operation in operations.iter() {
+                if let Operation::Write(bytes) = operation {
+                    written.extend_from_slice(bytes);
+                }
+            }
+            self.log.push((address, written.clone()));
+            if address == TCA9548A_DEFAULT_ADDRESS {
+                self.selected = written[0];
+                return Ok(());
+            }
+            let channel = self.selected.trailing_zeros() as usize;
+            match self.channels.get_mut(channel).and_then(Option::as_mut) {
+                Some(sensor) => sensor.transaction(address, operations),
+                None => Err(SimError(ErrorKind::NoAcknowledge(
+                    NoAcknowledgeSource::Address,
+                ))),
+            }
+        }
+    }
+
+    #[derive(Clone)]
+    struct NoDelay;
+
+    impl DelayNs for NoDelay {
+        fn delay_ns(&mut self, _ns: u32) {}
+    }
+
+    fn id(channel: u8) -> SensorId {
+        SensorId {
+            channel,
+            address: DeviceAddress::Primary,
+        }
+    }
+
+    #[test]
+    fn channels_above_7_are_rejected() {
+        let bus = RefCell::new(());
+        assert!(MuxChannel::new(&bus, TCA9548A_DEFAULT_ADDRESS, 7).is_some());
+        assert!(MuxChannel::new(&bus, TCA9548A_DEFAULT_ADDRESS, 8).is_none());
+        assert!(MuxChannel::new(&bus, TCA9548A_DEFAULT_ADDRESS, 255).is_none());
+    }
+
+    #[test]
+    fn array_rejects_channels_above_7() {
+        let bus = RefCell::new(MuxBus {
+            channels: Default::default(),
+            selected: 0,
+            log: Vec::new(),
+        });
+        let array = Ens160Array::new(&bus, TCA9548A_DEFAULT_ADDRESS, [id(0), id(8)], NoDelay);
+        assert!(array.is_none());
+    }
+
+    #[test]
+    fn one_result_per_sensor_in_order() {
+        let sim0 = RefCell::new(Ens160Sim::new(SimConfig::default(), &[]));
+        let sim3 = RefCell::new(Ens160Sim::new(SimConfig::default(), &[]));
+        let mut channels: [Option<SimI2c>; 8] = Default::default();
+        channels[0] = Some(SimI2c::new(&sim0));
+        channels[3] = Some(SimI2c::new(&sim3));
+        let bus = RefCell::new(MuxBus {
+            channels,
+            selected: 0,
+            log: Vec::new(),
+        });
+        // nothing on channel 5
+        let ids = [id(3), id(5), id(0)];
+        let mut array = Ens160Array::new(&bus, TCA9548A_DEFAULT_ADDRESS, ids, NoDelay).unwrap();
+        assert_eq!(array.ids(), ids);
+
+        let results = array.initialize_all(&Ens160Config::default());
+        assert_eq!(results.each_ref().map(|(id, _)| *id), ids);
+        assert_eq!(results[0].1.as_ref().unwrap().part_id, 0x0160);
+        assert!(matches!(
+            results[1].1,
+            Err(Error::I2c {
+                source: SimError(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address)),
+                ..
+            })
+        ));
+        assert_eq!(results[2].1.as_ref().unwrap().part_id, 0x0160);
+
+        sim0.borrow_mut().advance(5000);
+        sim3.borrow_mut().advance(5000);
+        let results = array.sample_all();
+        assert_eq!(results.each_ref().map(|(id, _)| *id), ids);
+        assert!(results[0].1.is_ok());
+        assert!(results[1].1.is_err());
+        assert!(results[2].1.is_ok());
+
+        // the mux is switched to the sensor's channel before every sensor transaction
+        let log = &bus.borrow().log;
+        assert!(!log.is_empty());
+        for (index, (address, written)) in log.iter().enumerate() {
+            if *address == TCA9548A_DEFAULT_ADDRESS {
+                let (next_address, _) = &log[index + 1];
+                assert_eq!(*next_address, 0x52);
+                assert_eq!(written.len(), 1);
+                assert_eq!(written[0].count_ones(), 1);
+            } else {
+                assert_eq!(log[index - 1].0, TCA9548A_DEFAULT_ADDRESS);
+            }
+        }
+        let selects: Vec<u8> = log
+            .iter()
+            .filter(|(address, _)| *address == TCA9548A_DEFAULT_ADDRESS)
+            .map(|(_, written)| written[0])
+            .collect();
+        // sensors are handled in id order, by initialize_all() and then by sample_all()
+        let mut order = selects.clone();
+        order.dedup();
+        assert_eq!(order, [1 << 3, 1 << 5, 1 << 0, 1 << 3, 1 << 5, 1 << 0]);
+        let sensor_selects = |mask: u8| selects.iter().filter(|&&s| s == mask).count();
+        assert!(sensor_selects(1 << 3) > 1);
+        assert!(sensor_selects(1 << 0) > 1);
+        // a missing sensor fails on its first transaction, once per call
+        assert_eq!(sensor_selects(1 << 5), 2);
+        assert_eq!(selects.len(), log.len() / 2);
+    }
+}
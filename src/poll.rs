@@ -0,0 +1,555 @@
+// non-blocking driver for super-loop firmware:  initialize, mode changes and reads are
+// state machines advanced by poll(now_ms), which never waits on the delayer.
+//
+//   let mut sensor = PollEns160::new(Ens160::new(i2c, delay));
+//   sensor.start_initialize(&Ens160Config::default());
+//   loop {
+//       match sensor.poll(millis()) {
+//           Ok(Completed::Initialized(info)) => sensor.start_read(),
+//           Ok(Completed::Measurements(measurements)) => { ...; sensor.start_read() }
+//           Ok(_) | Err(nb::Error::WouldBlock) => {}
+//           Err(nb::Error::Other(err)) => { ... }
+//       }
+//       // other super-loop work
+//   }
+
+use embedded_hal::{delay::DelayNs, i2c::I2c};
+use log::{debug, info};
+
+use crate::command::{ClearGpr, GetAppVersion, Nop};
+use crate::config::{DeviceInfo, Ens160Config, Variant};
+use crate::constants::ENS160_OPMODE;
+use crate::data::{Measurements, OperationMode};
+use crate::error::Error;
+use crate::firmware::FirmwareVersion;
+use crate::Ens160;
+
+/// time between status reads while waiting for new data in ms
+pub const DATA_POLL_MS: u32 = 100;
+
+/// what a poll() finished
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Completed {
+    /// no operation was started, see start_initialize(), start_set_mode() and start_read()
+    NoOperation,
+    /// start_initialize() is done
+    Initialized(DeviceInfo),
+    /// start_set_mode() is done, the mode read back from the sensor
+    ModeSet(OperationMode),
+    /// start_read() is done
+    Measurements(Measurements),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum InitStep {
+    EnterIdle,
+    ConfirmIdle,
+    ClearGroupData,
+    GetFirmware,
+    ReadFirmware { waited_ms: u32 },
+    Configure,
+    CheckMode,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Operation {
+    None,
+    Initialize(InitStep),
+    SetMode { mode: OperationMode, written: bool },
+    Read,
+}
+
+/// Ens160 driven by poll(now_ms) instead of blocking delays.  Start one operation, then call
+/// poll() from the super-loop:  it returns WouldBlock until the next step is due and the
+/// result once the operation is done.  Starting an operation abandons the one in progress.
+/// now_ms is a monotonic millisecond time (see sample::Clock), extend 32 bit tick counters
+/// to u64 so they do not wrap.
+pub struct PollEns160<I2C, D> {
+    sensor: Ens160<I2C, D>,
+    config: Ens160Config,
+    operation: Operation,
+    due_ms: Option<u64>,
+    part_id: u16,
+    variant: Variant,
+    firmware: Option<FirmwareVersion>,
+}
+
+impl<I2C, D, E> PollEns160<I2C, D>
+where
+    I2C: I2c<Error = E>,
+    D: DelayNs,
+{
+    /// non-blocking driver for sensor, the sensor's delayer is not used
+    pub fn new(sensor: Ens160<I2C, D>) -> Self {
+        PollEns160 {
+            sensor,
+            config: Ens160Config::default(),
+            operation: Operation::None,
+            due_ms: None,
+            part_id: 0,
+            variant: Variant::Ens160,
+            firmware: None,
+        }
+    }
+
+    /// the wrapped sensor, for calls that do not wait (e.g. get_status())
+    pub fn sensor_mut(&mut self) -> &mut Ens160<I2C, D> {
+        &mut self.sensor
+    }
+
+    /// give back the wrapped sensor
+    pub fn into_inner(self) -> Ens160<I2C, D> {
+        self.sensor
+    }
+
+    /// true while an operation is in progress
+    pub fn is_busy(&self) -> bool {
+        self.operation != Operation::None
+    }
+
+    /// start initialize_with(config), poll() returns Completed::Initialized when done
+    pub fn start_initialize(&mut self, config: &Ens160Config) {
+        self.config = *config;
+        self.sensor.timing = config.timing;
+        self.firmware = None;
+        self.start(Operation::Initialize(InitStep::EnterIdle));
+    }
+
+    /// start a change of the operating mode, poll() returns Completed::ModeSet when done
+    pub fn start_set_mode(&mut self, mode: OperationMode) {
+        self.start(Operation::SetMode {
+            mode,
+            written: false,
+        });
+    }
+
+    /// wait for new data and read it, poll() returns Completed::Measurements when done
+    pub fn start_read(&mut self) {
+        self.start(Operation::Read);
+    }
+
+    fn start(&mut self, operation: Operation) {
+        self.operation = operation;
+        self.due_ms = None;
+    }
+
+    /// advance the operation in progress, never waits.  An error ends the operation.
+    pub fn poll(&mut self, now_ms: u64) -> nb::Result<Completed, Error<E>> {
+        if let Some(due_ms) = self.due_ms {
+            if (now_ms.wrapping_sub(due_ms) as i64) < 0 {
+                return Err(nb::Error::WouldBlock);
+            }
+        }
+        let result = match self.operation {
+            Operation::None => return Ok(Completed::NoOperation),
+            Operation::Initialize(step) => self.initialize_step(step),
+            Operation::SetMode { mode, written } => self.set_mode_step(mode, written),
+            Operation::Read => self.read_step(),
+        };
+        match result {
+            Ok(Step::Wait(wait_ms)) => {
+                self.due_ms = Some(now_ms.wrapping_add(wait_ms as u64));
+                Err(nb::Error::WouldBlock)
+            }
+            Ok(Step::Done(completed)) => {
+                self.start(Operation::None);
+                Ok(completed)
+            }
+            Err(err) => {
+                self.start(Operation::None);
+                Err(nb::Error::Other(err))
+            }
+        }
+    }
+
+    // the steps of initialize_with(), each followed by its wait
+    fn initialize_step(&mut self, step: InitStep) -> Result<Step, Error<E>> {
+        let timing = self.sensor.timing;
+        let (next, wait_ms) = match step {
+            InitStep::EnterIdle => {
+                self.sensor
+                    .write_command([ENS160_OPMODE, OperationMode::Idle as u8])?;
+                (InitStep::ConfirmIdle, timing.mode_change_ms)
+            }
+            InitStep::ConfirmIdle => {
+                self.check_mode(OperationMode::Idle)?;
+                self.part_id = self.sensor.get_part_id()?;
+                self.variant = Variant::from_part_id(self.part_id)
+                    .ok_or(Error::UnexpectedChipId(self.part_id))?;
+                info!("ENS160 part id is good {:#06x}", self.part_id);
+                (InitStep::ClearGroupData, timing.idle_ms)
+            }
+            InitStep::ClearGroupData => {
+                // the sensor is idle (ConfirmIdle), commands are sent without execute()'s waits
+                self.sensor.send_command(&Nop)?;
+                self.sensor.send_command(&ClearGpr)?;
+                (InitStep::GetFirmware, timing.clear_gpr_ms)
+            }
+            InitStep::GetFirmware => {
+                self.sensor.send_command(&GetAppVersion)?;
+                (
+                    InitStep::ReadFirmware { waited_ms: 0 },
+                    self.sensor.command_wait_ms(),
+                )
+            }
+            InitStep::ReadFirmware { waited_ms } => {
+                // poll for command completion like get_firmware_version()
+                if !self.sensor.get_status()?.new_group_data_ready()
+                    && waited_ms < timing.command_timeout_ms
+                {
+                    let interval_ms = timing.poll_interval_ms.max(1);
+                    self.operation = Operation::Initialize(InitStep::ReadFirmware {
+                        waited_ms: waited_ms + interval_ms,
+                    });
+                    return Ok(Step::Wait(interval_ms));
+                }
+                let firmware = self.sensor.read_firmware_version()?;
+                info!("firmware version {}", firmware);
+                if let Some(minimum) = self.config.min_firmware {
+                    if firmware < minimum {
+                        return Err(Error::UnsupportedFirmware(firmware));
+                    }
+                }
+                self.firmware = Some(firmware);
+                (InitStep::Configure, timing.firmware_ms)
+            }
+            InitStep::Configure => {
+                if let Some(int_config) = self.config.interrupt_config {
+                    let read_back = self.sensor.config_interrupt_pin(int_config)?;
+                    if read_back != int_config {
+                        return Err(Error::InterruptConfigNotCorrect(read_back));
+                    }
+                }
+                if let Some((temp_c, rh_percent)) = self.config.compensation {
+                    self.sensor.set_temp_rh_comp(temp_c, rh_percent)?;
+                }
+                if self.config.operation_mode == OperationMode::Idle {
+                    return Ok(Step::Done(Completed::Initialized(self.device_info())));
+                }
+                self.sensor
+                    .write_command([ENS160_OPMODE, self.config.operation_mode as u8])?;
+                (InitStep::CheckMode, timing.standard_ms)
+            }
+            InitStep::CheckMode => {
+                self.check_mode(self.config.operation_mode)?;
+                return Ok(Step::Done(Completed::Initialized(self.device_info())));
+            }
+        };
+        self.operation = Operation::Initialize(next);
+        Ok(Step::Wait(wait_ms))
+    }
+
+    fn set_mode_step(&mut self, mode: OperationMode, written: bool) -> Result<Step, Error<E>> {
+        if !written {
+            debug!("setting ens160 operation mode to {:#?}", mode);
+            self.sensor.write_command([ENS160_OPMODE, mode as u8])?;
+            self.operation = Operation::SetMode {
+                mode,
+                written: true,
+            };
+            return Ok(Step::Wait(self.sensor.timing.mode_change_ms));
+        }
+        if mode == OperationMode::Reset {
+            // the sensor does not stay in reset, there is nothing to read back
+            return Ok(Step::Done(Completed::ModeSet(mode)));
+        }
+        self.check_mode(mode)?;
+        Ok(Step::Done(Completed::ModeSet(mode)))
+    }
+
+    fn read_step(&mut self) -> Result<Step, Error<E>> {
+        if !self.sensor.get_status()?.new_data_ready() {
+            return Ok(Step::Wait(DATA_POLL_MS));
+        }
+        let measurements = self.sensor.get_measurements()?;
+        Ok(Step::Done(Completed::Measurements(measurements)))
+    }
+
+    // OPMODE read back must be mode
+    fn check_mode(&mut self, mode: OperationMode) -> Result<(), Error<E>> {
+        let mut result_buf: [u8; 1] = [0; 1];
+        self.sensor.read_address(ENS160_OPMODE, &mut result_buf)?;
+        if result_buf[0] != mode as u8 {
+            return Err(Error::OpModeNotCorrect(result_buf[0]));
+        }
+        Ok(())
+    }
+
+    fn device_info(&self) -> DeviceInfo {
+        DeviceInfo {
+            part_id: self.part_id,
+            variant: self.variant,
+            firmware: self.firmware,
+        }
+    }
+}
+
+// result of one state machine step
+enum Step {
+    Wait(u32),
+    Done(Completed),
+}
+
+#[cfg(all(test, feature = "sim"))]
+mod tests {
+    use core::cell::RefCell;
+
+    use super::*;
+    use crate::config::Timing;
+    use crate::constants::{ENS160_CONFIG, ENS160_DEVICE_STATUS, ENS160_PART_ID, ENS160_RH_IN};
+    use crate::sim::{
+        Ens160Sim, SimConfig, SimDelay, SimError, SimEvent, SimEventKind, SimFault, Tamper,
+    };
+
+    type SimPoll<'s, 'e> = PollEns160<Tamper<'s, 'e>, SimDelay<'s, 'e>>;
+
+    fn poll_sensor<'s, 'e>(
+        sim: &'s RefCell<Ens160Sim<'e>>,
+        on_read: fn(u8, &mut [u8]),
+    ) -> SimPoll<'s, 'e> {
+        PollEns160::new(Ens160::new(Tamper::new(sim, on_read), SimDelay::new(sim)))
+    }
+
+    fn untouched(_register: u8, _buffer: &mut [u8]) {}
+
+    // poll every ms of simulated time from now_ms until the operation is done,
+    // returns the result and the time it took
+    fn run(
+        sensor: &mut SimPoll,
+        sim: &RefCell<Ens160Sim>,
+        now_ms: u64,
+    ) -> (Result<Completed, Error<SimError>>, u64) {
+        let mut elapsed_ms = 0;
+        loop {
+            match sensor.poll(now_ms.wrapping_add(elapsed_ms)) {
+                Err(nb::Error::WouldBlock) => {
+                    sim.borrow_mut().advance(1);
+                    elapsed_ms += 1;
+                    assert!(elapsed_ms < 10_000, "operation does not finish");
+                }
+                Err(nb::Error::Other(err)) => return (Err(err), elapsed_ms),
+                Ok(completed) => return (Ok(completed), elapsed_ms),
+            }
+        }
+    }
+
+    fn opmode(sensor: &mut SimPoll) -> u8 {
+        let mut buffer = [0u8; 1];
+        sensor
+            .sensor_mut()
+            .read_address(ENS160_OPMODE, &mut buffer)
+            .unwrap();
+        buffer[0]
+    }
+
+    fn info(firmware: FirmwareVersion) -> Completed {
+        Completed::Initialized(DeviceInfo {
+            part_id: 0x0160,
+            variant: Variant::Ens160,
+            firmware: Some(firmware),
+        })
+    }
+
+    #[test]
+    fn nothing_to_do() {
+        let sim = RefCell::new(Ens160Sim::new(SimConfig::default(), &[]));
+        let mut sensor = poll_sensor(&sim, untouched);
+        assert!(!sensor.is_busy());
+        assert_eq!(sensor.poll(0).unwrap(), Completed::NoOperation);
+        assert_eq!(sensor.sensor_mut().i2c.transactions, 0);
+    }
+
+    #[test]
+    fn initialize_steps() {
+        let sim = RefCell::new(Ens160Sim::new(SimConfig::default(), &[]));
+        let mut sensor = poll_sensor(&sim, untouched);
+        let config = Ens160Config::builder()
+            .interrupt_config(0x23)
+            .compensation(21.0, 40.0)
+            .build();
+        sensor.start_initialize(&config);
+        assert!(sensor.is_busy());
+        let (result, elapsed_ms) = run(&mut sensor, &sim, 0);
+        assert_eq!(result.unwrap(), info(FirmwareVersion::TESTED));
+        assert!(!sensor.is_busy());
+        // every wait of initialize_with(), the command completes before the first status poll
+        let timing = Timing::legacy();
+        let waits = timing.mode_change_ms
+            + timing.idle_ms
+            + timing.clear_gpr_ms
+            + timing.command_ms
+            + timing.firmware_ms
+            + timing.standard_ms;
+        assert_eq!(elapsed_ms, waits as u64);
+        assert_eq!(opmode(&mut sensor), OperationMode::Standard as u8);
+        let mut int_config = [0u8; 1];
+        sensor
+            .sensor_mut()
+            .read_address(ENS160_CONFIG, &mut int_config)
+            .unwrap();
+        assert_eq!(int_config[0], 0x23);
+        let mut rh_in = [0u8; 2];
+        sensor
+            .sensor_mut()
+            .read_address(ENS160_RH_IN, &mut rh_in)
+            .unwrap();
+        assert_eq!(u16::from_le_bytes(rh_in), 40 * 512);
+    }
+
+    #[test]
+    fn initialize_to_idle() {
+        let sim = RefCell::new(Ens160Sim::new(SimConfig::default(), &[]));
+        let mut sensor = poll_sensor(&sim, untouched);
+        sensor.start_initialize(
+            &Ens160Config::builder()
+                .operation_mode(OperationMode::Idle)
+                .build(),
+        );
+        let (result, _) = run(&mut sensor, &sim, 0);
+        assert_eq!(result.unwrap(), info(FirmwareVersion::TESTED));
+        assert_eq!(opmode(&mut sensor), OperationMode::Idle as u8);
+    }
+
+    #[test]
+    fn idle_is_confirmed() {
+        fn stays_asleep(register: u8, buffer: &mut [u8]) {
+            if register == ENS160_OPMODE {
+                buffer[0] = OperationMode::Sleep as u8;
+            }
+        }
+        let sim = RefCell::new(Ens160Sim::new(SimConfig::default(), &[]));
+        let mut sensor = poll_sensor(&sim, stays_asleep);
+        sensor.start_initialize(&Ens160Config::default());
+        let (result, elapsed_ms) = run(&mut sensor, &sim, 0);
+        assert!(matches!(result, Err(Error::OpModeNotCorrect(0x00))));
+        assert_eq!(elapsed_ms, Timing::legacy().mode_change_ms as u64);
+        assert!(!sensor.is_busy());
+    }
+
+    #[test]
+    fn unexpected_part_id() {
+        fn other_chip(register: u8, buffer: &mut [u8]) {
+            if register == ENS160_PART_ID {
+                buffer.copy_from_slice(&[0x34, 0x12]);
+            }
+        }
+        let sim = RefCell::new(Ens160Sim::new(SimConfig::default(), &[]));
+        let mut sensor = poll_sensor(&sim, other_chip);
+        sensor.start_initialize(&Ens160Config::default());
+        assert!(matches!(
+            run(&mut sensor, &sim, 0).0,
+            Err(Error::UnexpectedChipId(0x1234))
+        ));
+    }
+
+    #[test]
+    fn firmware_too_old() {
+        let sim = RefCell::new(Ens160Sim::new(SimConfig::default(), &[]));
+        let mut sensor = poll_sensor(&sim, untouched);
+        let minimum = FirmwareVersion::new(9, 0, 0);
+        sensor.start_initialize(&Ens160Config::builder().min_firmware(minimum).build());
+        assert!(matches!(
+            run(&mut sensor, &sim, 0).0,
+            Err(Error::UnsupportedFirmware(FirmwareVersion::TESTED))
+        ));
+    }
+
+    #[test]
+    fn command_timeout_falls_back_to_reading() {
+        // the command never reports completion, the version is read after command_timeout_ms
+        fn no_group_data(register: u8, buffer: &mut [u8]) {
+            if register == ENS160_DEVICE_STATUS {
+                buffer[0] &= !0x01;
+            }
+        }
+        let sim = RefCell::new(Ens160Sim::new(SimConfig::default(), &[]));
+        let mut sensor = poll_sensor(&sim, no_group_data);
+        sensor.start_initialize(&Ens160Config::default());
+        let (result, elapsed_ms) = run(&mut sensor, &sim, 0);
+        assert_eq!(result.unwrap(), info(FirmwareVersion::TESTED));
+        let timing = Timing::legacy();
+        let waits = timing.mode_change_ms
+            + timing.idle_ms
+            + timing.clear_gpr_ms
+            + timing.command_ms
+            + timing.command_timeout_ms
+            + timing.firmware_ms
+            + timing.standard_ms;
+        assert_eq!(elapsed_ms, waits as u64);
+    }
+
+    #[test]
+    fn waits_without_bus_traffic() {
+        let sim = RefCell::new(Ens160Sim::new(SimConfig::default(), &[]));
+        let mut sensor = poll_sensor(&sim, untouched);
+        sensor.start_initialize(&Ens160Config::default());
+        assert!(matches!(sensor.poll(1000), Err(nb::Error::WouldBlock)));
+        let transactions = sensor.sensor_mut().i2c.transactions;
+        assert!(matches!(sensor.poll(1000), Err(nb::Error::WouldBlock)));
+        assert!(matches!(sensor.poll(1049), Err(nb::Error::WouldBlock)));
+        assert_eq!(sensor.sensor_mut().i2c.transactions, transactions);
+        assert!(matches!(sensor.poll(1050), Err(nb::Error::WouldBlock)));
+        assert!(sensor.sensor_mut().i2c.transactions > transactions);
+    }
+
+    #[test]
+    fn now_ms_wraparound() {
+        let sim = RefCell::new(Ens160Sim::new(SimConfig::default(), &[]));
+        let mut sensor = poll_sensor(&sim, untouched);
+        sensor.start_initialize(&Ens160Config::default());
+        let (_, expected_ms) = run(&mut sensor, &sim, 0);
+        for start_ms in [u32::MAX as u64 - 100, u64::MAX - 100] {
+            sensor.start_initialize(&Ens160Config::default());
+            let (result, elapsed_ms) = run(&mut sensor, &sim, start_ms);
+            assert_eq!(result.unwrap(), info(FirmwareVersion::TESTED));
+            assert_eq!(elapsed_ms, expected_ms);
+        }
+    }
+
+    #[test]
+    fn set_mode_and_read() {
+        let sim = RefCell::new(Ens160Sim::new(SimConfig::default(), &[]));
+        let mut sensor = poll_sensor(&sim, untouched);
+        sensor.start_initialize(
+            &Ens160Config::builder()
+                .operation_mode(OperationMode::Idle)
+                .build(),
+        );
+        run(&mut sensor, &sim, 0).0.unwrap();
+        sensor.start_set_mode(OperationMode::Standard);
+        let (result, elapsed_ms) = run(&mut sensor, &sim, 0);
+        assert_eq!(result.unwrap(), Completed::ModeSet(OperationMode::Standard));
+        assert_eq!(elapsed_ms, Timing::legacy().mode_change_ms as u64);
+        sensor.start_read();
+        let (result, elapsed_ms) = run(&mut sensor, &sim, 0);
+        assert!(matches!(result, Ok(Completed::Measurements(_))));
+        assert!(elapsed_ms <= 1000 + DATA_POLL_MS as u64);
+        assert_eq!(sensor.poll(0).unwrap(), Completed::NoOperation);
+    }
+
+    #[test]
+    fn error_ends_operation() {
+        const MISSING: [SimEvent; 1] = [SimEvent {
+            at_ms: 0,
+            kind: SimEventKind::Fault {
+                fault: SimFault::NoAcknowledge,
+                duration_ms: 60_000,
+            },
+        }];
+        let sim = RefCell::new(Ens160Sim::new(SimConfig::default(), &MISSING));
+        sim.borrow_mut().advance(1);
+        let mut sensor = poll_sensor(&sim, untouched);
+        sensor.start_initialize(&Ens160Config::default());
+        let (result, elapsed_ms) = run(&mut sensor, &sim, 0);
+        assert!(matches!(
+            result,
+            Err(Error::I2c {
+                register: ENS160_OPMODE,
+                ..
+            })
+        ));
+        assert_eq!(elapsed_ms, 0);
+        assert!(!sensor.is_busy());
+        assert_eq!(sensor.poll(0).unwrap(), Completed::NoOperation);
+    }
+}
@@ -0,0 +1,472 @@
+// I2C transaction recorder and replay bus:  field captures become deterministic regression tests
+//
+// Log format, one transaction per line, hex bytes without separators:
+//
+//   # ens160-aq i2c log v1
+//   <time ms> <address> w<bytes> r<bytes> ... [!<error>]
+//
+// e.g. "1520 52 w20 r82" is a write of 0x20 followed by a read of 0x82 from address 0x52.
+// A failed transaction ends with !nack-addr, !nack-data, !nack (source unknown), !bus,
+// !arbitration, !overrun or !other.
+
+use std::fmt::Write as _;
+use std::io::{self, BufRead, Write};
+use std::time::Instant;
+use std::vec::Vec;
+
+use embedded_hal::delay::DelayNs;
+use embedded_hal::i2c::{Error, ErrorKind, ErrorType, I2c, NoAcknowledgeSource, Operation};
+
+/// first line of a log
+pub const LOG_HEADER: &str = "# ens160-aq i2c log v1";
+
+/// I2C bus wrapper that logs every transaction the driver performs, with a timestamp
+/// in ms since the recorder was created.  Use it in place of the bus:
+/// Ens160::new(I2cRecorder::new(i2c, file), delay)
+pub struct I2cRecorder<I2C, W: Write> {
+    i2c: I2C,
+    log: W,
+    start: Instant,
+    log_error: Option<io::Error>,
+}
+
+impl<I2C, W: Write> I2cRecorder<I2C, W> {
+    /// record the transactions on i2c into log
+    pub fn new(i2c: I2C, mut log: W) -> Self {
+        let log_error = writeln!(log, "{}", LOG_HEADER).err();
+        I2cRecorder {
+            i2c,
+            log,
+            start: Instant::now(),
+            log_error,
+        }
+    }
+
+    /// first error writing the log, if any (bus transactions are not failed because of it)
+    pub fn log_error(&self) -> Option<&io::Error> {
+        self.log_error.as_ref()
+    }
+
+    /// give back the bus and the log
+    pub fn release(self) -> (I2C, W) {
+        (self.i2c, self.log)
+    }
+}
+
+impl<I2C: ErrorType, W: Write> ErrorType for I2cRecorder<I2C, W> {
+    type Error = I2C::Error;
+}
+
+impl<I2C: I2c, W: Write> I2c for I2cRecorder<I2C, W> {
+    fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        let result = self.i2c.transaction(address, operations);
+        let mut line = format!("{} {:02x}", self.start.elapsed().as_millis(), address);
+        for operation in operations.iter() {
+            let (prefix, bytes): (char, &[u8]) = match operation {
+                Operation::Write(bytes) => ('w', bytes),
+                Operation::Read(bytes) => ('r', bytes),
+            };
+            line.push(' ');
+            line.push(prefix);
+            for byte in bytes {
+                let _ = write!(line, "{:02x}", byte);
+            }
+        }
+        if let Err(err) = &result {
+            line.push_str(" !");
+            line.push_str(kind_name(err.kind()));
+        }
+        if let Err(err) = writeln!(self.log, "{}", line) {
+            self.log_error.get_or_insert(err);
+        }
+        result
+    }
+}
+
+/// errors of the ReplayBus
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplayError {
+    /// the transaction differs from the recorded one (log line number)
+    Mismatch(usize),
+    /// the driver performed more transactions than recorded
+    EndOfLog,
+    /// the recorded transaction failed with this error
+    Recorded(ErrorKind),
+}
+
+impl Error for ReplayError {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            ReplayError::Recorded(kind) => *kind,
+            _ => ErrorKind::Other,
+        }
+    }
+}
+
+/// log line that could not be parsed (line number)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseError(pub usize);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum RecordedOperation {
+    Write(Vec<u8>),
+    Read(Vec<u8>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Transaction {
+    line: usize,
+    time_ms: u64,
+    address: u8,
+    operations: Vec<RecordedOperation>,
+    error: Option<ErrorKind>,
+}
+
+/// I2C bus that plays back a log written by I2cRecorder:  writes are checked against the
+/// log and reads return the recorded bytes, so the driver sees exactly what it saw in the field.
+#[derive(Debug, Clone)]
+pub struct ReplayBus {
+    transactions: Vec<Transaction>,
+    next: usize,
+}
+
+impl ReplayBus {
+    /// parse a log
+    pub fn parse(log: &str) -> Result<Self, ParseError> {
+        let mut transactions = Vec::new();
+        for (index, line) in log.lines().enumerate() {
+            let line_number = index + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            transactions.push(parse_line(line, line_number).ok_or(ParseError(line_number))?);
+        }
+        Ok(ReplayBus {
+            transactions,
+            next: 0,
+        })
+    }
+
+    /// read and parse a log
+    pub fn from_reader<R: BufRead>(reader: R) -> io::Result<Self> {
+        let log = io::read_to_string(reader)?;
+        Self::parse(&log).map_err(|ParseError(line)| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("bad log line {}", line))
+        })
+    }
+
+    /// recorded time (ms) of the last replayed transaction, for analytics that need timestamps
+    pub fn time_ms(&self) -> u64 {
+        self.next
+            .checked_sub(1)
+            .map_or(0, |last| self.transactions[last].time_ms)
+    }
+
+    /// transactions not replayed yet
+    pub fn remaining(&self) -> usize {
+        self.transactions.len() - self.next
+    }
+
+    /// true once every recorded transaction has been replayed
+    pub fn is_done(&self) -> bool {
+        self.remaining() == 0
+    }
+}
+
+impl ErrorType for ReplayBus {
+    type Error = ReplayError;
+}
+
+impl I2c for ReplayBus {
+    fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        let recorded = self.transactions.get(self.next).ok_or(ReplayError::EndOfLog)?;
+        let mismatch = ReplayError::Mismatch(recorded.line);
+        if recorded.address != address || recorded.operations.len() != operations.len() {
+            return Err(mismatch);
+        }
+        for (operation, recorded_operation) in operations.iter_mut().zip(&recorded.operations) {
+            match (operation, recorded_operation) {
+                (Operation::Write(bytes), RecordedOperation::Write(recorded_bytes))
+                    if bytes == recorded_bytes => {}
+                (Operation::Read(buffer), RecordedOperation::Read(recorded_bytes))
+                    if buffer.len() == recorded_bytes.len() =>
+                {
+                    buffer.copy_from_slice(recorded_bytes)
+                }
+                _ => return Err(mismatch),
+            }
+        }
+        self.next += 1;
+        match recorded.error {
+            Some(kind) => Err(ReplayError::Recorded(kind)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// delay that returns at once, replays do not need to wait for the sensor
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoDelay;
+
+impl DelayNs for NoDelay {
+    fn delay_ns(&mut self, _ns: u32) {}
+}
+
+fn parse_line(line: &str, line_number: usize) -> Option<Transaction> {
+    let mut fields = line.split_whitespace();
+    let time_ms = fields.next()?.parse().ok()?;
+    let address = u8::from_str_radix(fields.next()?, 16).ok()?;
+    let mut operations = Vec::new();
+    let mut error = None;
+    for field in fields {
+        if let Some(name) = field.strip_prefix('!') {
+            error = Some(kind_from_name(name)?);
+        } else if let Some(hex) = field.strip_prefix('w') {
+            operations.push(RecordedOperation::Write(parse_hex(hex)?));
+        } else if let Some(hex) = field.strip_prefix('r') {
+            operations.push(RecordedOperation::Read(parse_hex(hex)?));
+        } else {
+            return None;
+        }
+    }
+    Some(Transaction {
+        line: line_number,
+        time_ms,
+        address,
+        operations,
+        error,
+    })
+}
+
+// None for an odd number of digits (the last pair is then out of range) or non hex digits
+fn parse_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|start| u8::from_str_radix(hex.get(start..start + 2)?, 16).ok())
+        .collect()
+}
+
+fn kind_name(kind: ErrorKind) -> &'static str {
+    match kind {
+        ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address) => "nack-addr",
+        ErrorKind::NoAcknowledge(NoAcknowledgeSource::Data) => "nack-data",
+        ErrorKind::NoAcknowledge(_) => "nack",
+        ErrorKind::Bus => "bus",
+        ErrorKind::ArbitrationLoss => "arbitration",
+        ErrorKind::Overrun => "overrun",
+        _ => "other",
+    }
+}
+
+fn kind_from_name(name: &str) -> Option<ErrorKind> {
+    match name {
+        "nack-addr" => Some(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address)),
+        "nack-data" => Some(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Data)),
+        "nack" => Some(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Unknown)),
+        "bus" => Some(ErrorKind::Bus),
+        "arbitration" => Some(ErrorKind::ArbitrationLoss),
+        "overrun" => Some(ErrorKind::Overrun),
+        "other" => Some(ErrorKind::Other),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::{Error as Ens160Error, ErrorClass, I2cOp};
+    use crate::Ens160;
+
+    #[test]
+    fn parse_hex_digits() {
+        assert_eq!(parse_hex(""), Some(vec![]));
+        assert_eq!(parse_hex("0a1B"), Some(vec![0x0a, 0x1b]));
+        assert_eq!(parse_hex("ff00"), Some(vec![0xff, 0x00]));
+        // odd digit counts
+        assert_eq!(parse_hex("0"), None);
+        assert_eq!(parse_hex("abc"), None);
+        // bad input
+        assert_eq!(parse_hex("0g"), None);
+        assert_eq!(parse_hex("+1"), None);
+        assert_eq!(parse_hex("-1"), None);
+        assert_eq!(parse_hex("0x12"), None);
+        assert_eq!(parse_hex("ü1"), None);
+    }
+
+    #[test]
+    fn parse_log_lines() {
+        let bus = ReplayBus::parse("# comment\n\n0 52 w00 r6001\n 15 53 w10\t !bus \n").unwrap();
+        assert_eq!(bus.remaining(), 2);
+        assert_eq!(
+            bus.transactions[1],
+            Transaction {
+                line: 4,
+                time_ms: 15,
+                address: 0x53,
+                operations: vec![RecordedOperation::Write(vec![0x10])],
+                error: Some(ErrorKind::Bus),
+            }
+        );
+        for (log, line) in [
+            ("0 52 x00", 1),
+            ("0 zz w00", 1),
+            ("# header\nms 52 w00", 2),
+            ("0 52 w0", 1),
+            ("0 52 w00 !timeout", 1),
+            ("0", 1),
+        ] {
+            assert_eq!(
+                ReplayBus::parse(log).unwrap_err(),
+                ParseError(line),
+                "{}",
+                log
+            );
+        }
+        // every error kind is written and parsed back
+        for kind in [
+            ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address),
+            ErrorKind::NoAcknowledge(NoAcknowledgeSource::Data),
+            ErrorKind::NoAcknowledge(NoAcknowledgeSource::Unknown),
+            ErrorKind::Bus,
+            ErrorKind::ArbitrationLoss,
+            ErrorKind::Overrun,
+            ErrorKind::Other,
+        ] {
+            let log = format!("0 52 w00 !{}", kind_name(kind));
+            let bus = ReplayBus::parse(&log).unwrap();
+            assert_eq!(bus.transactions[0].error, Some(kind), "{}", log);
+        }
+        let err = ReplayBus::from_reader("0 52 w00 r6\n".as_bytes()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn replay_recorded_error() {
+        let log = format!("{}\n0 52 w00 r0000 !nack\n", LOG_HEADER);
+        let mut ens160 = Ens160::new(ReplayBus::parse(&log).unwrap(), NoDelay);
+        match ens160.get_part_id() {
+            Err(Ens160Error::I2c {
+                op: I2cOp::Read,
+                register: 0x00,
+                source: ReplayError::Recorded(ErrorKind::NoAcknowledge(_)),
+            }) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(ens160.release().is_done());
+    }
+
+    #[test]
+    fn replay_missing_device() {
+        let log = format!("{}\n0 52 w00 r0000 !nack-addr\n", LOG_HEADER);
+        let mut ens160 = Ens160::new(ReplayBus::parse(&log).unwrap(), NoDelay);
+        let err = ens160.get_part_id().unwrap_err();
+        assert_eq!(err.classify(), ErrorClass::DeviceMissing);
+    }
+
+    #[test]
+    fn replay_mismatch() {
+        let log = format!("{}\n0 52 w00 r6001\n", LOG_HEADER);
+        // different register
+        let mut ens160 = Ens160::new(ReplayBus::parse(&log).unwrap(), NoDelay);
+        assert!(matches!(
+            ens160.get_status(),
+            Err(Ens160Error::I2c {
+                source: ReplayError::Mismatch(2),
+                ..
+            })
+        ));
+        assert_eq!(ens160.release().remaining(), 1);
+        // different address
+        let mut bus = ReplayBus::parse(&log).unwrap();
+        let mut buffer = [0u8; 2];
+        assert_eq!(
+            bus.write_read(0x53, &[0x00], &mut buffer),
+            Err(ReplayError::Mismatch(2))
+        );
+        // different read length
+        let mut buffer = [0u8; 1];
+        assert_eq!(
+            bus.write_read(0x52, &[0x00], &mut buffer),
+            Err(ReplayError::Mismatch(2))
+        );
+        // more transactions than recorded
+        let mut buffer = [0u8; 2];
+        assert_eq!(bus.write_read(0x52, &[0x00], &mut buffer), Ok(()));
+        assert_eq!(buffer, [0x60, 0x01]);
+        assert_eq!(
+            bus.write_read(0x52, &[0x00], &mut buffer),
+            Err(ReplayError::EndOfLog)
+        );
+    }
+
+    // record a session on the simulator, then replay it without the simulator
+    #[cfg(feature = "sim")]
+    mod sim {
+        use core::cell::RefCell;
+
+        use super::*;
+        use crate::data::Measurements;
+        use crate::sim::{Ens160Sim, SimConfig, SimDelay, SimI2c};
+
+        fn record_session() -> (String, Measurements) {
+            let sim = RefCell::new(Ens160Sim::new(SimConfig::default(), &[]));
+            let recorder = I2cRecorder::new(SimI2c::new(&sim), Vec::new());
+            let mut ens160 = Ens160::new(recorder, SimDelay::new(&sim));
+            assert!(ens160.initialize().unwrap());
+            sim.borrow_mut().advance(5_000);
+            let measurements = ens160.get_measurements().unwrap();
+            let recorder = ens160.release();
+            assert!(recorder.log_error().is_none());
+            let (_, log) = recorder.release();
+            (String::from_utf8(log).unwrap(), measurements)
+        }
+
+        #[test]
+        fn recorder_parse_round_trip() {
+            let (log, _) = record_session();
+            let lines: Vec<&str> = log.lines().collect();
+            assert_eq!(lines[0], LOG_HEADER);
+            let bus = ReplayBus::parse(&log).unwrap();
+            assert_eq!(bus.remaining(), lines.len() - 1);
+            for (transaction, line) in bus.transactions.iter().zip(&lines[1..]) {
+                assert_eq!(transaction.address, 0x52);
+                assert_eq!(transaction.error, None);
+                // written back in the log format gives the same line
+                let mut written = format!("{} {:02x}", transaction.time_ms, transaction.address);
+                for operation in &transaction.operations {
+                    let (prefix, bytes) = match operation {
+                        RecordedOperation::Write(bytes) => ('w', bytes),
+                        RecordedOperation::Read(bytes) => ('r', bytes),
+                    };
+                    written.push(' ');
+                    written.push(prefix);
+                    for byte in bytes {
+                        let _ = write!(written, "{:02x}", byte);
+                    }
+                }
+                assert_eq!(&written, line);
+            }
+        }
+
+        #[test]
+        fn replay_initialize_and_measurements() {
+            let (log, measurements) = record_session();
+            let mut ens160 = Ens160::new(ReplayBus::parse(&log).unwrap(), NoDelay);
+            assert!(ens160.initialize().unwrap());
+            assert_eq!(ens160.get_measurements().unwrap(), measurements);
+            assert!(ens160.release().is_done());
+        }
+    }
+}
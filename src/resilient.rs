@@ -0,0 +1,380 @@
+// opt-in wrapper around Ens160 that retries failed I2C transactions and
+// re-initializes the sensor when it stops responding
+
+use crate::config::{DeviceInfo, Ens160Config};
+use crate::constants::ENS160_OPMODE;
+use crate::data::{Measurements, Status};
+use crate::error::{Error, ErrorClass};
+use crate::Ens160;
+
+#[cfg(not(feature = "async"))]
+use embedded_hal::{delay::DelayNs, i2c::Error as I2cError, i2c::I2c};
+#[cfg(feature = "async")]
+use embedded_hal_async::{
+    delay::DelayNs as AsyncDelayNs, i2c::Error as I2cError, i2c::I2c as AsyncI2c,
+};
+
+use log::{debug, warn};
+
+/// retry and recovery settings of a ResilientEns160
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// how many times a failed call is retried before its error is returned
+    pub max_retries: u8,
+    /// delay before the first retry in ms, doubled for every further retry
+    pub backoff_ms: u32,
+    /// number of consecutive transient failures after which the sensor is re-initialized
+    pub reinit_after: u8,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: 3,
+            backoff_ms: 10,
+            reinit_after: 5,
+        }
+    }
+}
+
+/// counters showing how often recovery was needed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RecoveryStats {
+    /// calls retried after a transient error or a missing device, see Error::classify()
+    pub retries: u32,
+    /// re-initializations of the sensor
+    pub reinitializations: u32,
+    /// re-initializations that failed themselves
+    pub failed_reinitializations: u32,
+    /// OPMODE read back as something other than the configured mode
+    pub opmode_mismatches: u32,
+}
+
+/// Ens160 wrapper that retries failed calls with backoff (using the driver's delayer),
+/// re-initializes the sensor after consecutive failures or an OPMODE mismatch with the
+/// last Ens160Config, updated by the interrupt pin configuration and compensation values.
+pub struct ResilientEns160<I2C, D> {
+    sensor: Ens160<I2C, D>,
+    policy: RetryPolicy,
+    stats: RecoveryStats,
+    consecutive_failures: u8,
+    config: Ens160Config,
+}
+
+#[maybe_async_cfg::maybe(
+    sync(
+        cfg(not(feature = "async")),
+        self = "ResilientEns160",
+        idents(AsyncI2c(sync = "I2c"), AsyncDelayNs(sync = "DelayNs"))
+    ),
+    async(feature = "async", keep_self)
+)]
+impl<I2C, D, E> ResilientEns160<I2C, D>
+where
+    I2C: AsyncI2c<Error = E>,
+    E: I2cError,
+    D: AsyncDelayNs,
+{
+    /// wrap an Ens160 driver using the given retry policy
+    pub fn new(sensor: Ens160<I2C, D>, policy: RetryPolicy) -> Self {
+        let config = Ens160Config {
+            timing: sensor.timing(),
+            ..Default::default()
+        };
+        ResilientEns160 {
+            sensor,
+            policy,
+            stats: RecoveryStats::default(),
+            consecutive_failures: 0,
+            config,
+        }
+    }
+
+    /// the configuration applied when the sensor is re-initialized
+    pub fn config(&self) -> Ens160Config {
+        self.config
+    }
+
+    /// recovery counters
+    pub fn stats(&self) -> RecoveryStats {
+        self.stats
+    }
+
+    /// the wrapped driver, calls made on it directly are not retried
+    pub fn sensor_mut(&mut self) -> &mut Ens160<I2C, D> {
+        &mut self.sensor
+    }
+
+    /// give back the wrapped driver
+    pub fn into_inner(self) -> Ens160<I2C, D> {
+        self.sensor
+    }
+
+    // called after a failed call, Ok means try again.  A missing device is retried too, the
+    // sensor does not answer while it restarts after a brown-out.
+    async fn on_failure(&mut self, error: Error<E>, attempt: &mut u8) -> Result<(), Error<E>> {
+        if error.classify() == ErrorClass::Permanent {
+            return Err(error);
+        }
+        self.consecutive_failures = self.consecutive_failures.saturating_add(1);
+        if self.consecutive_failures >= self.policy.reinit_after {
+            self.recover().await;
+        }
+        if *attempt >= self.policy.max_retries {
+            return Err(error);
+        }
+        let backoff_ms = self.policy.backoff_ms.saturating_mul(1 << (*attempt).min(16));
+        *attempt += 1;
+        self.stats.retries = self.stats.retries.saturating_add(1);
+        debug!("ENS160 transient error, retry {} after {} ms", attempt, backoff_ms);
+        self.sensor.delayer.delay_ms(backoff_ms).await;
+        Ok(())
+    }
+
+    fn on_success(&mut self) {
+        self.consecutive_failures = 0;
+    }
+
+    // re-initialize the sensor with the last configuration
+    async fn recover(&mut self) {
+        warn!("ENS160 not responding, re-initializing");
+        self.stats.reinitializations = self.stats.reinitializations.saturating_add(1);
+        self.consecutive_failures = 0;
+        if self.sensor.initialize_with(&self.config).await.is_err() {
+            self.stats.failed_reinitializations =
+                self.stats.failed_reinitializations.saturating_add(1);
+        }
+    }
+
+    /// initialize the ENS160 device with the default configuration and the current timing,
+    /// retried on I2C errors
+    pub async fn initialize(&mut self) -> Result<bool, Error<E>> {
+        let config = Ens160Config {
+            timing: self.sensor.timing(),
+            ..Default::default()
+        };
+        self.initialize_with(&config).await?;
+        Ok(true)
+    }
+
+    /// initialize the ENS160 device with config, retried on I2C errors.  The config is
+    /// applied again after a re-initialization.
+    pub async fn initialize_with(&mut self, config: &Ens160Config) -> Result<DeviceInfo, Error<E>> {
+        self.config = *config;
+        let mut attempt = 0;
+        loop {
+            match self.sensor.initialize_with(config).await {
+                Ok(info) => {
+                    self.on_success();
+                    return Ok(info);
+                }
+                Err(error) => self.on_failure(error, &mut attempt).await?,
+            }
+        }
+    }
+
+    /// configure the interrupt pin, the configuration is restored after a re-initialization
+    pub async fn config_interrupt_pin(&mut self, config: u8) -> Result<u8, Error<E>> {
+        self.config.interrupt_config = Some(config);
+        let mut attempt = 0;
+        loop {
+            match self.sensor.config_interrupt_pin(config).await {
+                Ok(result) => {
+                    self.on_success();
+                    return Ok(result);
+                }
+                Err(error) => self.on_failure(error, &mut attempt).await?,
+            }
+        }
+    }
+
+    /// set temperature and humidity compensation, the values are restored after a re-initialization
+    pub async fn set_temp_rh_comp(
+        &mut self,
+        temp_c: f32,
+        rh_percent: f32,
+    ) -> Result<(), Error<E>> {
+        self.config.compensation = Some((temp_c, rh_percent));
+        let mut attempt = 0;
+        loop {
+            match self.sensor.set_temp_rh_comp(temp_c, rh_percent).await {
+                Ok(()) => {
+                    self.on_success();
+                    return Ok(());
+                }
+                Err(error) => self.on_failure(error, &mut attempt).await?,
+            }
+        }
+    }
+
+    /// get ENS160 status flags, retried on I2C errors
+    pub async fn get_status(&mut self) -> Result<Status, Error<E>> {
+        let mut attempt = 0;
+        loop {
+            match self.sensor.get_status().await {
+                Ok(status) => {
+                    self.on_success();
+                    return Ok(status);
+                }
+                Err(error) => self.on_failure(error, &mut attempt).await?,
+            }
+        }
+    }
+
+    /// get all measurements from sensor, retried on I2C errors
+    pub async fn get_measurements(&mut self) -> Result<Measurements, Error<E>> {
+        let mut attempt = 0;
+        loop {
+            match self.sensor.get_measurements().await {
+                Ok(measurements) => {
+                    self.on_success();
+                    return Ok(measurements);
+                }
+                Err(error) => self.on_failure(error, &mut attempt).await?,
+            }
+        }
+    }
+
+    /// read back OPMODE and re-initialize the sensor if it is not in the configured mode
+    /// (e.g. after a brown-out reset of the ENS160).  Returns true if the mode was correct.
+    pub async fn check_operation_mode(&mut self) -> Result<bool, Error<E>> {
+        let mut result_buf: [u8; 1] = [0; 1];
+        let mut attempt = 0;
+        loop {
+            match self.sensor.read_address(ENS160_OPMODE, &mut result_buf).await {
+                Ok(()) => {
+                    self.on_success();
+                    break;
+                }
+                Err(error) => self.on_failure(error, &mut attempt).await?,
+            }
+        }
+        if result_buf[0] == self.config.operation_mode as u8 {
+            Ok(true)
+        } else {
+            warn!("ENS160 OPMODE read back as {:#04x}", result_buf[0]);
+            self.stats.opmode_mismatches = self.stats.opmode_mismatches.saturating_add(1);
+            self.recover().await;
+            Ok(false)
+        }
+    }
+}
+
+#[cfg(all(test, feature = "sim", not(feature = "async")))]
+mod tests {
+    use core::cell::RefCell;
+
+    use super::*;
+    use crate::config::Timing;
+    use crate::constants::ENS160_CONFIG;
+    use crate::data::OperationMode;
+    use crate::sim::{
+        Ens160Sim, SimConfig, SimDelay, SimEvent, SimEventKind, SimFault, SimI2c, Tamper,
+    };
+
+    type SimEns160<'s, 'e> = Ens160<SimI2c<'s, 'e>, SimDelay<'s, 'e>>;
+
+    fn driver<'s, 'e>(sim: &'s RefCell<Ens160Sim<'e>>) -> SimEns160<'s, 'e> {
+        Ens160::new(SimI2c::new(sim), SimDelay::new(sim))
+    }
+
+    fn register(sensor: &mut SimEns160, address: u8) -> u8 {
+        let mut buffer = [0u8; 1];
+        sensor.read_address(address, &mut buffer).unwrap();
+        buffer[0]
+    }
+
+    #[test]
+    fn initialize_keeps_timing() {
+        let sim = RefCell::new(Ens160Sim::new(SimConfig::default(), &[]));
+        let mut ens160 = driver(&sim);
+        ens160.set_timing(Timing::fast());
+        assert!(ens160.initialize().unwrap());
+        assert_eq!(ens160.timing(), Timing::fast());
+
+        let mut resilient = ResilientEns160::new(ens160, RetryPolicy::default());
+        assert_eq!(resilient.config().timing, Timing::fast());
+        assert!(resilient.initialize().unwrap());
+        assert_eq!(resilient.sensor_mut().timing(), Timing::fast());
+    }
+
+    #[test]
+    fn recovery_applies_the_last_config() {
+        const BROWN_OUT: [SimEvent; 1] = [SimEvent {
+            at_ms: 10_000,
+            kind: SimEventKind::Fault {
+                fault: SimFault::Reset,
+                duration_ms: 0,
+            },
+        }];
+        let sim = RefCell::new(Ens160Sim::new(SimConfig::default(), &BROWN_OUT));
+        let mut resilient = ResilientEns160::new(driver(&sim), RetryPolicy::default());
+        let config = Ens160Config::builder()
+            .operation_mode(OperationMode::Idle)
+            .timing(Timing::conservative())
+            .build();
+        let info = resilient.initialize_with(&config).unwrap();
+        assert_eq!(info.part_id, 0x0160);
+        resilient.config_interrupt_pin(0x23).unwrap();
+        resilient.set_temp_rh_comp(21.0, 40.0).unwrap();
+        assert!(resilient.check_operation_mode().unwrap());
+
+        sim.borrow_mut().advance(10_000);
+        // the brown-out put the sensor back to deep sleep
+        assert!(!resilient.check_operation_mode().unwrap());
+        let stats = resilient.stats();
+        assert_eq!(stats.opmode_mismatches, 1);
+        assert_eq!(stats.reinitializations, 1);
+        assert_eq!(stats.failed_reinitializations, 0);
+        assert!(resilient.check_operation_mode().unwrap());
+
+        let applied = resilient.config();
+        assert_eq!(applied.operation_mode, OperationMode::Idle);
+        assert_eq!(applied.interrupt_config, Some(0x23));
+        assert_eq!(applied.compensation, Some((21.0, 40.0)));
+        let sensor = resilient.sensor_mut();
+        assert_eq!(sensor.timing(), Timing::conservative());
+        assert_eq!(register(sensor, ENS160_OPMODE), OperationMode::Idle as u8);
+        assert_eq!(register(sensor, ENS160_CONFIG), 0x23);
+        let (temp_c, rh_percent) = sensor.get_temp_rh_comp().unwrap();
+        assert!((temp_c - 21.0).abs() < 1.0 / 64.0);
+        assert_eq!(rh_percent, 40.0);
+    }
+
+    #[test]
+    fn garbage_opmode_read_back_is_an_error() {
+        fn garbage_opmode(register: u8, buffer: &mut [u8]) {
+            if register == ENS160_OPMODE {
+                buffer[0] = 0xff;
+            }
+        }
+        let sim = RefCell::new(Ens160Sim::new(SimConfig::default(), &[]));
+        let mut ens160 = Ens160::new(Tamper::new(&sim, garbage_opmode), SimDelay::new(&sim));
+        assert!(matches!(
+            ens160.set_operation_mode(OperationMode::Idle),
+            Err(Error::OpModeNotCorrect(0xff))
+        ));
+        assert!(matches!(
+            ens160.initialize(),
+            Err(Error::OpModeNotCorrect(0xff))
+        ));
+
+        let policy = RetryPolicy {
+            max_retries: 3,
+            backoff_ms: 10,
+            reinit_after: 2,
+        };
+        let mut resilient = ResilientEns160::new(ens160, policy);
+        assert!(matches!(
+            resilient.initialize(),
+            Err(Error::OpModeNotCorrect(0xff))
+        ));
+        let stats = resilient.stats();
+        assert_eq!(stats.retries, 3);
+        assert_eq!(stats.reinitializations, 2);
+        assert_eq!(stats.failed_reinitializations, 2);
+        assert!(!resilient.check_operation_mode().unwrap());
+        assert_eq!(resilient.stats().opmode_mismatches, 1);
+        assert_eq!(resilient.stats().failed_reinitializations, 3);
+    }
+}
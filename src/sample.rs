@@ -0,0 +1,40 @@
+// timestamped and sequenced measurements, so loggers can tell a new chip cycle from a
+// repeated read.  The clock is only needed by read_sample() and read_latest_sample().
+//
+//   let start = std::time::Instant::now();
+//   let mut clock = || start.elapsed().as_millis() as u64;
+//   if let Some(sample) = ens160.read_sample(&mut clock)? { log(sample); }
+
+use crate::data::Measurements;
+
+/// monotonic millisecond clock (e.g. a timer tick counter or the time since boot)
+pub trait Clock {
+    /// current time in ms, must never go backwards
+    fn now_ms(&mut self) -> u64;
+}
+
+impl<F: FnMut() -> u64> Clock for F {
+    fn now_ms(&mut self) -> u64 {
+        self()
+    }
+}
+
+/// measurements of one chip cycle (the ENS160 measures once per second in standard mode)
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Sample {
+    /// the measured values
+    pub measurements: Measurements,
+    /// clock time in ms when the chip cycle was first read
+    pub timestamp_ms: u64,
+    /// number of the chip cycle, counted by the driver from 1, the same for repeated reads
+    pub sequence: u32,
+    /// ms since timestamp_ms when this sample was returned, 0 for a new chip cycle
+    pub age_ms: u64,
+}
+
+impl Sample {
+    /// age of the sample at the given clock time
+    pub fn age_at(&self, now_ms: u64) -> u64 {
+        now_ms.saturating_sub(self.timestamp_ms)
+    }
+}
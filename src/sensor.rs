@@ -0,0 +1,94 @@
+// generic air quality sensor interface, so application code can be written once for
+// ENS160, SGP40, CCS811, ... boards.  Blocking or async, following the crate features.
+
+use crate::data::{AirQualityIndex, ValidityFlag};
+use crate::error::Error;
+use crate::Ens160;
+
+#[cfg(not(feature = "async"))]
+use embedded_hal::{delay::DelayNs, i2c::I2c};
+#[cfg(feature = "async")]
+use embedded_hal_async::{delay::DelayNs as AsyncDelayNs, i2c::I2c as AsyncI2c};
+
+/// air quality sensor measuring eCO2 and TVOC.  Sensors without an own air quality index
+/// return AirQualityIndex::Unavailable, sensors without compensation inputs ignore it.
+#[maybe_async_cfg::maybe(
+    sync(cfg(not(feature = "async")), self = "AirQualitySensor"),
+    async(feature = "async", keep_self)
+)]
+#[allow(async_fn_in_trait)]
+pub trait AirQualitySensor {
+    /// error type of the sensor driver
+    type Error;
+
+    /// equivalent CO2 in ppm
+    async fn eco2_ppm(&mut self) -> Result<u16, Self::Error>;
+
+    /// total volatile organic compounds in ppb
+    async fn tvoc_ppb(&mut self) -> Result<u16, Self::Error>;
+
+    /// air quality index according to UBA
+    async fn air_quality_index(&mut self) -> Result<AirQualityIndex, Self::Error>;
+
+    /// true once the sensor output is valid (e.g. after warm-up)
+    async fn is_ready(&mut self) -> Result<bool, Self::Error>;
+
+    /// true if a new measurement is available since the last read
+    async fn data_ready(&mut self) -> Result<bool, Self::Error>;
+
+    /// ambient temperature and relative humidity for compensation
+    async fn set_compensation(
+        &mut self,
+        temperature: f32,
+        humidity: f32,
+    ) -> Result<(), Self::Error>;
+}
+
+#[maybe_async_cfg::maybe(
+    sync(
+        cfg(not(feature = "async")),
+        self = "Ens160",
+        idents(AsyncI2c(sync = "I2c"), AsyncDelayNs(sync = "DelayNs"))
+    ),
+    async(feature = "async", keep_self)
+)]
+impl<I2C, D, E> AirQualitySensor for Ens160<I2C, D>
+where
+    I2C: AsyncI2c<Error = E>,
+    D: AsyncDelayNs,
+{
+    type Error = Error<E>;
+
+    async fn eco2_ppm(&mut self) -> Result<u16, Error<E>> {
+        Ok(self.get_eco2().await?.get_value())
+    }
+
+    async fn tvoc_ppb(&mut self) -> Result<u16, Error<E>> {
+        self.get_tvoc().await
+    }
+
+    async fn air_quality_index(&mut self) -> Result<AirQualityIndex, Error<E>> {
+        self.get_airquality_index().await
+    }
+
+    /// valid in normal operation and (with less accuracy) in the initial start-up phase
+    async fn is_ready(&mut self) -> Result<bool, Error<E>> {
+        let validity = self.get_status().await?.validity_flag();
+        Ok(matches!(
+            validity,
+            ValidityFlag::NormalOperation | ValidityFlag::InitialStartupPhase
+        ))
+    }
+
+    async fn data_ready(&mut self) -> Result<bool, Error<E>> {
+        Ok(self.get_status().await?.new_data_ready())
+    }
+
+    async fn set_compensation(
+        &mut self,
+        temperature: f32,
+        humidity: f32,
+    ) -> Result<(), Error<E>> {
+        self.set_temp_rh_comp(temperature, humidity).await
+    }
+}
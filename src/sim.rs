@@ -0,0 +1,776 @@
+// behavioural ENS160 simulator:  an I2C device with warm-up, start-up validity, scripted
+// occupancy and VOC events, noise and faults, driven by simulated time.
+//
+//   let sim = RefCell::new(Ens160Sim::new(SimConfig::default(), &EVENTS));
+//   let mut ens160 = Ens160::new(SimI2c::new(&sim), SimDelay::new(&sim));
+//   ens160.initialize()?;
+//   sim.borrow_mut().advance(5 * 60 * 1000);  // five minutes later
+//   let measurements = ens160.get_measurements()?;
+
+use core::cell::RefCell;
+
+use embedded_hal::delay::DelayNs;
+use embedded_hal::i2c::{Error, ErrorKind, ErrorType, I2c, NoAcknowledgeSource, Operation};
+use libm::{expf, log2f, sqrtf};
+
+use crate::constants::{
+    DeviceAddress, ENS160_COMMAND, ENS160_CONFIG, ENS160_DATA_AQI, ENS160_DATA_ECO2,
+    ENS160_DATA_RH, ENS160_DATA_T, ENS160_DEVICE_STATUS, ENS160_GPR_READ, ENS160_GPR_WRITE,
+    ENS160_OPMODE, ENS160_PART_ID, ENS160_RH_IN, ENS160_TEMP_IN,
+};
+use crate::data::{ENS160Command, OperationMode, HOT_PLATES};
+use crate::firmware::FirmwareVersion;
+
+/// standard mode warm-up time (datasheet: 3 minutes)
+pub const WARMUP_MS: u64 = 3 * 60 * 1000;
+/// initial start-up phase of a new sensor (datasheet: first hour of operation)
+pub const INITIAL_STARTUP_MS: u64 = 60 * 60 * 1000;
+/// standard mode measurement cycle
+pub const CYCLE_MS: u64 = 1000;
+
+const MAX_VOC_RELEASES: usize = 8;
+
+/// simulator settings
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SimConfig {
+    /// I2C address the simulated sensor answers at
+    pub address: DeviceAddress,
+    /// firmware version reported to GetAppVersion
+    pub firmware: FirmwareVersion,
+    /// new sensor:  goes through the initial start-up phase after warm-up
+    pub initial_startup: bool,
+    /// eCO2 of fresh air in ppm
+    pub outdoor_eco2_ppm: f32,
+    /// steady state eCO2 increase per person in ppm
+    pub eco2_per_person_ppm: f32,
+    /// ventilation time constant in ms
+    pub ventilation_ms: f32,
+    /// TVOC of clean air in ppb
+    pub baseline_tvoc_ppb: f32,
+    /// clean air hot plate resistance in ohms
+    pub clean_air_resistance_ohm: f32,
+    /// noise amplitude as a fraction of the value (e.g. 0.02 is 2 %)
+    pub noise: f32,
+    /// seed of the noise generator, same seed gives the same run
+    pub seed: u32,
+}
+
+impl Default for SimConfig {
+    fn default() -> Self {
+        SimConfig {
+            address: DeviceAddress::Primary,
+            firmware: FirmwareVersion::TESTED,
+            initial_startup: false,
+            outdoor_eco2_ppm: 420.0,
+            eco2_per_person_ppm: 250.0,
+            ventilation_ms: 20.0 * 60.0 * 1000.0,
+            baseline_tvoc_ppb: 50.0,
+            clean_air_resistance_ohm: 100_000.0,
+            noise: 0.02,
+            seed: 0x1605_2024,
+        }
+    }
+}
+
+/// sensor faults the simulator can inject
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SimFault {
+    /// the sensor does not acknowledge its address
+    NoAcknowledge,
+    /// measurement values stop changing
+    StuckValues,
+    /// the error bit of the status register is set
+    StatusError,
+    /// brown-out:  the sensor falls back to deep sleep and must be initialized again
+    Reset,
+}
+
+/// what happens in a scripted event
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SimEventKind {
+    /// number of people in the room from now on
+    Occupancy(u8),
+    /// VOC release (cleaning, cooking, ...) adding ppb TVOC, decaying with the time constant
+    VocRelease { ppb: f32, decay_ms: f32 },
+    /// fault for duration_ms (Reset happens once)
+    Fault { fault: SimFault, duration_ms: u32 },
+}
+
+/// scripted event at a simulated time
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SimEvent {
+    /// simulated time in ms, events must be in time order
+    pub at_ms: u64,
+    /// what happens
+    pub kind: SimEventKind,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct VocRelease {
+    start_ms: u64,
+    ppb: f32,
+    decay_ms: f32,
+}
+
+/// the simulated ENS160, share it between SimI2c and SimDelay through a RefCell
+#[derive(Debug)]
+pub struct Ens160Sim<'e> {
+    config: SimConfig,
+    events: &'e [SimEvent],
+    next_event: usize,
+    time_ms: u64,
+    opmode: u8,
+    standard_since_ms: u64,
+    run_time_ms: u64,
+    next_cycle_ms: u64,
+    int_config: u8,
+    temp_in: u16,
+    rh_in: u16,
+    gpr_write: [u8; 8],
+    gpr_read: [u8; 8],
+    new_data: bool,
+    new_gpr: bool,
+    people: u8,
+    eco2_ppm: f32,
+    voc_releases: [Option<VocRelease>; MAX_VOC_RELEASES],
+    faults: [Option<u64>; 3],
+    data: [u8; 5],
+    rng: u32,
+}
+
+impl<'e> Ens160Sim<'e> {
+    /// a powered, deep sleeping sensor at simulated time 0 following the events script
+    pub fn new(config: SimConfig, events: &'e [SimEvent]) -> Self {
+        Ens160Sim {
+            config,
+            events,
+            next_event: 0,
+            time_ms: 0,
+            opmode: OperationMode::Sleep as u8,
+            standard_since_ms: 0,
+            run_time_ms: 0,
+            next_cycle_ms: 0,
+            int_config: 0,
+            temp_in: 0x4a4d, // 25 C
+            rh_in: 0x6400,   // 50 %
+            gpr_write: [0; 8],
+            gpr_read: [0; 8],
+            new_data: false,
+            new_gpr: false,
+            people: 0,
+            eco2_ppm: config.outdoor_eco2_ppm,
+            voc_releases: [None; MAX_VOC_RELEASES],
+            faults: [None; 3],
+            data: [0; 5],
+            rng: config.seed | 1,
+        }
+    }
+
+    /// simulated time in ms
+    pub fn time_ms(&self) -> u64 {
+        self.time_ms
+    }
+
+    /// true eCO2 of the simulated room (without noise) in ppm
+    pub fn true_eco2_ppm(&self) -> f32 {
+        self.eco2_ppm
+    }
+
+    /// true TVOC of the simulated room (without noise) in ppb
+    pub fn true_tvoc_ppb(&self) -> f32 {
+        let voc: f32 = self
+            .voc_releases
+            .iter()
+            .flatten()
+            .map(|release| {
+                let age = self.time_ms.saturating_sub(release.start_ms) as f32;
+                release.ppb * expf(-age / release.decay_ms)
+            })
+            .sum();
+        self.config.baseline_tvoc_ppb + voc
+    }
+
+    /// let simulated time pass
+    pub fn advance(&mut self, ms: u64) {
+        let end_ms = self.time_ms.saturating_add(ms);
+        while self.time_ms < end_ms {
+            let step_ms = (end_ms - self.time_ms).min(CYCLE_MS);
+            self.step(step_ms);
+        }
+    }
+
+    // one step of at most one cycle
+    fn step(&mut self, step_ms: u64) {
+        self.time_ms += step_ms;
+        while let Some(event) = self.events.get(self.next_event) {
+            if event.at_ms > self.time_ms {
+                break;
+            }
+            self.next_event += 1;
+            self.apply(event.kind);
+        }
+        for fault in self.faults.iter_mut() {
+            if matches!(fault, Some(until_ms) if *until_ms <= self.time_ms) {
+                *fault = None;
+            }
+        }
+
+        // first order ventilation model of the room air
+        let target =
+            self.config.outdoor_eco2_ppm + self.people as f32 * self.config.eco2_per_person_ppm;
+        self.eco2_ppm +=
+            (target - self.eco2_ppm) * (1.0 - expf(-(step_ms as f32) / self.config.ventilation_ms));
+        for release in self.voc_releases.iter_mut() {
+            if let Some(voc) = release {
+                if self.time_ms.saturating_sub(voc.start_ms) as f32 > 10.0 * voc.decay_ms {
+                    *release = None;
+                }
+            }
+        }
+
+        if self.opmode == OperationMode::Standard as u8 {
+            self.run_time_ms += step_ms;
+            if self.time_ms >= self.next_cycle_ms {
+                self.next_cycle_ms = self.time_ms + CYCLE_MS;
+                self.measure();
+            }
+        }
+    }
+
+    fn apply(&mut self, kind: SimEventKind) {
+        match kind {
+            SimEventKind::Occupancy(people) => self.people = people,
+            SimEventKind::VocRelease { ppb, decay_ms } => {
+                let release = VocRelease {
+                    start_ms: self.time_ms,
+                    ppb,
+                    decay_ms: decay_ms.max(1.0),
+                };
+                // replace a free slot or the oldest release
+                let slot = self
+                    .voc_releases
+                    .iter()
+                    .position(Option::is_none)
+                    .unwrap_or_else(|| {
+                        let mut oldest = 0;
+                        for (index, voc) in self.voc_releases.iter().enumerate() {
+                            if let (Some(voc), Some(current)) = (voc, &self.voc_releases[oldest]) {
+                                if voc.start_ms < current.start_ms {
+                                    oldest = index;
+                                }
+                            }
+                        }
+                        oldest
+                    });
+                self.voc_releases[slot] = Some(release);
+            }
+            SimEventKind::Fault { fault, duration_ms } => match fault {
+                SimFault::Reset => {
+                    self.opmode = OperationMode::Sleep as u8;
+                    self.new_data = false;
+                    self.new_gpr = false;
+                }
+                _ => self.faults[fault as usize] = Some(self.time_ms + duration_ms as u64),
+            },
+        }
+    }
+
+    fn fault_active(&self, fault: SimFault) -> bool {
+        fault != SimFault::Reset && self.faults[fault as usize].is_some()
+    }
+
+    // validity flag for the status register, invalid output unless measuring
+    fn validity(&self) -> u8 {
+        let running_ms = self.time_ms - self.standard_since_ms;
+        if self.opmode != OperationMode::Standard as u8 {
+            0x03
+        } else if running_ms < WARMUP_MS {
+            0x01
+        } else if self.config.initial_startup && self.run_time_ms < INITIAL_STARTUP_MS {
+            0x02
+        } else {
+            0x00
+        }
+    }
+
+    // a standard mode measurement cycle
+    fn measure(&mut self) {
+        if self.fault_active(SimFault::StuckValues) {
+            self.new_data = true;
+            return;
+        }
+        let eco2 = self.noisy(self.eco2_ppm).clamp(400.0, 65000.0);
+        let tvoc = self.noisy(self.true_tvoc_ppb()).clamp(0.0, 65000.0);
+        let aqi = match eco2 as u16 {
+            0..=600 => 1,
+            601..=800 => 2,
+            801..=1000 => 3,
+            1001..=1500 => 4,
+            _ => 5,
+        };
+        self.data[0] = aqi;
+        self.data[1..3].copy_from_slice(&(tvoc as u16).to_le_bytes());
+        self.data[3..5].copy_from_slice(&(eco2 as u16).to_le_bytes());
+
+        // hot plate resistance falls with the VOC concentration, each plate a little differently
+        for plate in 0..HOT_PLATES {
+            let sensitivity = 0.5 + 0.1 * plate as f32;
+            let clean = self.config.clean_air_resistance_ohm * (1.0 + 0.2 * plate as f32);
+            let resistance = self.noisy(clean / sqrtf(1.0 + sensitivity * tvoc / 100.0));
+            let raw = (log2f(resistance.max(1.0)) * 2048.0) as u16;
+            self.gpr_read[plate * 2..plate * 2 + 2].copy_from_slice(&raw.to_le_bytes());
+        }
+        self.new_data = true;
+        self.new_gpr = true;
+    }
+
+    // value with uniform noise of +- config.noise
+    fn noisy(&mut self, value: f32) -> f32 {
+        // xorshift32
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 17;
+        self.rng ^= self.rng << 5;
+        let uniform = (self.rng as f32 / u32::MAX as f32) * 2.0 - 1.0;
+        value * (1.0 + uniform * self.config.noise)
+    }
+
+    fn read_register(&mut self, address: u8) -> u8 {
+        match address {
+            a if a == ENS160_PART_ID => 0x60,
+            a if a == ENS160_PART_ID + 1 => 0x01,
+            a if a == ENS160_OPMODE => self.opmode,
+            a if a == ENS160_CONFIG => self.int_config,
+            a if a == ENS160_TEMP_IN || a == ENS160_DATA_T => self.temp_in.to_le_bytes()[0],
+            a if a == ENS160_TEMP_IN + 1 || a == ENS160_DATA_T + 1 => self.temp_in.to_le_bytes()[1],
+            a if a == ENS160_RH_IN || a == ENS160_DATA_RH => self.rh_in.to_le_bytes()[0],
+            a if a == ENS160_RH_IN + 1 || a == ENS160_DATA_RH + 1 => self.rh_in.to_le_bytes()[1],
+            a if a == ENS160_DEVICE_STATUS => {
+                let running = self.opmode == OperationMode::Standard as u8;
+                (running as u8) << 7
+                    | (self.fault_active(SimFault::StatusError) as u8) << 6
+                    | self.validity() << 2
+                    | (self.new_data as u8) << 1
+                    | self.new_gpr as u8
+            }
+            a if (ENS160_DATA_AQI..ENS160_DATA_ECO2 + 2).contains(&a) => {
+                self.new_data = false;
+                self.data[(a - ENS160_DATA_AQI) as usize]
+            }
+            a if (ENS160_GPR_WRITE..ENS160_GPR_WRITE + 8).contains(&a) => {
+                self.gpr_write[(a - ENS160_GPR_WRITE) as usize]
+            }
+            a if (ENS160_GPR_READ..ENS160_GPR_READ + 8).contains(&a) => {
+                self.new_gpr = false;
+                self.gpr_read[(a - ENS160_GPR_READ) as usize]
+            }
+            _ => 0,
+        }
+    }
+
+    fn write_register(&mut self, address: u8, value: u8) {
+        match address {
+            a if a == ENS160_OPMODE => self.set_opmode(value),
+            a if a == ENS160_CONFIG => self.int_config = value,
+            a if a == ENS160_COMMAND => self.command(value),
+            a if a == ENS160_TEMP_IN => self.temp_in = self.temp_in & 0xff00 | value as u16,
+            a if a == ENS160_TEMP_IN + 1 => {
+                self.temp_in = self.temp_in & 0x00ff | (value as u16) << 8
+            }
+            a if a == ENS160_RH_IN => self.rh_in = self.rh_in & 0xff00 | value as u16,
+            a if a == ENS160_RH_IN + 1 => self.rh_in = self.rh_in & 0x00ff | (value as u16) << 8,
+            a if (ENS160_GPR_WRITE..ENS160_GPR_WRITE + 8).contains(&a) => {
+                self.gpr_write[(a - ENS160_GPR_WRITE) as usize] = value
+            }
+            _ => {}
+        }
+    }
+
+    fn set_opmode(&mut self, value: u8) {
+        if value == OperationMode::Reset as u8 {
+            // factory reset:  back to deep sleep and a new sensor's start-up phase
+            self.opmode = OperationMode::Sleep as u8;
+            self.run_time_ms = 0;
+            self.config.initial_startup = true;
+            return;
+        }
+        if value == OperationMode::Standard as u8 && self.opmode != value {
+            // entering standard mode restarts the warm-up
+            self.standard_since_ms = self.time_ms;
+            self.next_cycle_ms = self.time_ms + CYCLE_MS;
+        }
+        if value <= OperationMode::Standard as u8 {
+            self.opmode = value;
+        }
+    }
+
+    fn command(&mut self, command: u8) {
+        // commands are only executed in idle mode
+        if self.opmode != OperationMode::Idle as u8 {
+            return;
+        }
+        if command == ENS160Command::GetAppVersion as u8 {
+            self.gpr_read[4] = self.config.firmware.major;
+            self.gpr_read[5] = self.config.firmware.minor;
+            self.gpr_read[6] = self.config.firmware.release;
+            self.new_gpr = true;
+        } else if command == ENS160Command::ClearGPR as u8 {
+            self.gpr_read = [0; 8];
+            self.new_gpr = false;
+        }
+    }
+
+    fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), SimError> {
+        if address != u8::from(self.config.address) || self.fault_active(SimFault::NoAcknowledge) {
+            return Err(SimError(ErrorKind::NoAcknowledge(
+                NoAcknowledgeSource::Address,
+            )));
+        }
+        let mut register = 0u8;
+        for operation in operations.iter_mut() {
+            match operation {
+                Operation::Write(bytes) => {
+                    if let Some((first, data)) = bytes.split_first() {
+                        register = *first;
+                        for value in data {
+                            self.write_register(register, *value);
+                            register = register.wrapping_add(1);
+                        }
+                    }
+                }
+                Operation::Read(buffer) => {
+                    for value in buffer.iter_mut() {
+                        *value = self.read_register(register);
+                        register = register.wrapping_add(1);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// I2C error of the simulator
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SimError(pub ErrorKind);
+
+impl Error for SimError {
+    fn kind(&self) -> ErrorKind {
+        self.0
+    }
+}
+
+/// I2C bus with the simulated ENS160 on it
+pub struct SimI2c<'s, 'e> {
+    sim: &'s RefCell<Ens160Sim<'e>>,
+}
+
+impl<'s, 'e> SimI2c<'s, 'e> {
+    /// bus with sim on it
+    pub fn new(sim: &'s RefCell<Ens160Sim<'e>>) -> Self {
+        SimI2c { sim }
+    }
+}
+
+impl ErrorType for SimI2c<'_, '_> {
+    type Error = SimError;
+}
+
+impl I2c for SimI2c<'_, '_> {
+    fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        self.sim.borrow_mut().transaction(address, operations)
+    }
+}
+
+/// delay that advances the simulated time instead of waiting
+pub struct SimDelay<'s, 'e> {
+    sim: &'s RefCell<Ens160Sim<'e>>,
+    remainder_ns: u32,
+}
+
+impl<'s, 'e> SimDelay<'s, 'e> {
+    /// delay advancing the time of sim
+    pub fn new(sim: &'s RefCell<Ens160Sim<'e>>) -> Self {
+        SimDelay {
+            sim,
+            remainder_ns: 0,
+        }
+    }
+}
+
+impl DelayNs for SimDelay<'_, '_> {
+    fn delay_ns(&mut self, ns: u32) {
+        let total_ns = self.remainder_ns as u64 + ns as u64;
+        self.remainder_ns = (total_ns % 1_000_000) as u32;
+        self.sim.borrow_mut().advance(total_ns / 1_000_000);
+    }
+}
+
+/// simulator bus that lets a test change what registers read back, counts transactions and
+/// records the registers written
+#[cfg(test)]
+pub(crate) struct Tamper<'s, 'e> {
+    bus: SimI2c<'s, 'e>,
+    on_read: fn(u8, &mut [u8]),
+    pub(crate) transactions: usize,
+    pub(crate) written: Vec<u8>,
+}
+
+#[cfg(test)]
+impl<'s, 'e> Tamper<'s, 'e> {
+    /// bus with sim on it, on_read gets the register address and the bytes read from it
+    pub(crate) fn new(sim: &'s RefCell<Ens160Sim<'e>>, on_read: fn(u8, &mut [u8])) -> Self {
+        Tamper {
+            bus: SimI2c::new(sim),
+            on_read,
+            transactions: 0,
+            written: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+impl ErrorType for Tamper<'_, '_> {
+    type Error = SimError;
+}
+
+#[cfg(test)]
+impl I2c for Tamper<'_, '_> {
+    fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), SimError> {
+        self.transactions += 1;
+        self.bus.transaction(address, operations)?;
+        match operations {
+            [Operation::Write(register), Operation::Read(buffer)] => {
+                (self.on_read)(register[0], buffer)
+            }
+            [Operation::Write(bytes)] => self.written.push(bytes[0]),
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::ValidityFlag;
+    use crate::units::{CentiCelsius, MilliPercent, RawResistance};
+    use crate::Ens160;
+
+    type SimEns160<'s, 'e> = Ens160<SimI2c<'s, 'e>, SimDelay<'s, 'e>>;
+
+    fn driver<'s, 'e>(sim: &'s RefCell<Ens160Sim<'e>>) -> SimEns160<'s, 'e> {
+        Ens160::new(SimI2c::new(sim), SimDelay::new(sim))
+    }
+
+    fn validity(ens160: &mut SimEns160) -> ValidityFlag {
+        ens160.get_status().unwrap().validity_flag()
+    }
+
+    fn fault(at_ms: u64, fault: SimFault, duration_ms: u32) -> SimEvent {
+        SimEvent {
+            at_ms,
+            kind: SimEventKind::Fault { fault, duration_ms },
+        }
+    }
+
+    #[test]
+    fn warmup_initial_startup_normal() {
+        let config = SimConfig {
+            initial_startup: true,
+            ..Default::default()
+        };
+        let sim = RefCell::new(Ens160Sim::new(config, &[]));
+        let mut ens160 = driver(&sim);
+        assert_eq!(validity(&mut ens160), ValidityFlag::InvalidOutput);
+        ens160.set_operation_mode(OperationMode::Standard).unwrap();
+        assert_eq!(validity(&mut ens160), ValidityFlag::WarmupPhase);
+        sim.borrow_mut().advance(WARMUP_MS - 1000);
+        assert_eq!(validity(&mut ens160), ValidityFlag::WarmupPhase);
+        sim.borrow_mut().advance(1000);
+        assert_eq!(validity(&mut ens160), ValidityFlag::InitialStartupPhase);
+        // the start-up phase counts standard mode run time, not time since the mode change
+        ens160.set_operation_mode(OperationMode::Idle).unwrap();
+        assert_eq!(validity(&mut ens160), ValidityFlag::InvalidOutput);
+        sim.borrow_mut().advance(INITIAL_STARTUP_MS);
+        ens160.set_operation_mode(OperationMode::Standard).unwrap();
+        assert_eq!(validity(&mut ens160), ValidityFlag::WarmupPhase);
+        sim.borrow_mut().advance(WARMUP_MS);
+        assert_eq!(validity(&mut ens160), ValidityFlag::InitialStartupPhase);
+        sim.borrow_mut().advance(INITIAL_STARTUP_MS - 2 * WARMUP_MS);
+        assert_eq!(validity(&mut ens160), ValidityFlag::NormalOperation);
+    }
+
+    #[test]
+    fn used_sensor_skips_initial_startup() {
+        let sim = RefCell::new(Ens160Sim::new(SimConfig::default(), &[]));
+        let mut ens160 = driver(&sim);
+        assert!(ens160.initialize().unwrap());
+        assert_eq!(validity(&mut ens160), ValidityFlag::WarmupPhase);
+        sim.borrow_mut().advance(WARMUP_MS);
+        assert_eq!(validity(&mut ens160), ValidityFlag::NormalOperation);
+        // a factory reset makes it a new sensor again
+        ens160.set_operation_mode(OperationMode::Reset).unwrap();
+        ens160.set_operation_mode(OperationMode::Standard).unwrap();
+        sim.borrow_mut().advance(WARMUP_MS);
+        assert_eq!(validity(&mut ens160), ValidityFlag::InitialStartupPhase);
+    }
+
+    #[test]
+    fn fault_no_acknowledge() {
+        let events = [fault(10_000, SimFault::NoAcknowledge, 5_000)];
+        let sim = RefCell::new(Ens160Sim::new(SimConfig::default(), &events));
+        let mut ens160 = driver(&sim);
+        assert!(ens160.get_part_id().is_ok());
+        sim.borrow_mut().advance(10_000);
+        let error = ens160.get_part_id().unwrap_err();
+        assert_eq!(
+            error.i2c_kind(),
+            Some(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address))
+        );
+        sim.borrow_mut().advance(5_000);
+        assert_eq!(ens160.get_part_id().unwrap(), 0x0160);
+    }
+
+    #[test]
+    fn fault_stuck_values() {
+        let events = [
+            SimEvent {
+                at_ms: 0,
+                kind: SimEventKind::Occupancy(4),
+            },
+            fault(60_000, SimFault::StuckValues, 30_000),
+        ];
+        let sim = RefCell::new(Ens160Sim::new(SimConfig::default(), &events));
+        let mut ens160 = driver(&sim);
+        ens160.initialize().unwrap();
+        sim.borrow_mut().advance(61_000);
+        let stuck = ens160.get_measurements().unwrap();
+        for _ in 0..10 {
+            sim.borrow_mut().advance(CYCLE_MS);
+            // the stuck sensor still reports new data
+            assert!(ens160.get_status().unwrap().new_data_ready());
+            assert_eq!(ens160.get_measurements().unwrap(), stuck);
+        }
+        sim.borrow_mut().advance(30_000);
+        assert_ne!(ens160.get_measurements().unwrap(), stuck);
+    }
+
+    #[test]
+    fn fault_status_error() {
+        let events = [fault(2_000, SimFault::StatusError, 3_000)];
+        let sim = RefCell::new(Ens160Sim::new(SimConfig::default(), &events));
+        let mut ens160 = driver(&sim);
+        assert!(!ens160.get_status().unwrap().error());
+        sim.borrow_mut().advance(2_000);
+        assert!(ens160.get_status().unwrap().error());
+        sim.borrow_mut().advance(3_000);
+        assert!(!ens160.get_status().unwrap().error());
+    }
+
+    #[test]
+    fn fault_reset() {
+        let events = [fault(WARMUP_MS + 5_000, SimFault::Reset, 0)];
+        let sim = RefCell::new(Ens160Sim::new(SimConfig::default(), &events));
+        let mut ens160 = driver(&sim);
+        ens160.initialize().unwrap();
+        sim.borrow_mut().advance(WARMUP_MS + 4_500);
+        assert_eq!(validity(&mut ens160), ValidityFlag::NormalOperation);
+        sim.borrow_mut().advance(1_000);
+        let status = ens160.get_status().unwrap();
+        assert!(!status.running_mode());
+        assert!(!status.new_data_ready());
+        assert_eq!(status.validity_flag(), ValidityFlag::InvalidOutput);
+        let mut opmode = [0u8; 1];
+        ens160
+            .read_register(crate::constants::Register::OpMode, &mut opmode)
+            .unwrap();
+        assert_eq!(opmode[0], OperationMode::Sleep as u8);
+        // the brown-out restarts the warm-up
+        assert!(ens160.initialize().unwrap());
+        assert_eq!(validity(&mut ens160), ValidityFlag::WarmupPhase);
+    }
+
+    // RH_IN is percent * 512 and TEMP_IN Kelvin * 64, the same for the f32 and fixed-point API
+    #[test]
+    fn compensation_registers() {
+        let sim = RefCell::new(Ens160Sim::new(SimConfig::default(), &[]));
+        let mut ens160 = driver(&sim);
+        ens160.set_temp_rh_comp(25.0, 50.0).unwrap();
+        assert_eq!(sim.borrow().temp_in, 19082);
+        assert_eq!(sim.borrow().rh_in, 0x6400);
+        ens160.set_temp_rh_comp(21.5, 45.5).unwrap();
+        assert_eq!(sim.borrow().rh_in, 23296);
+        let (temp_c, rh_percent) = ens160.get_temp_rh_comp().unwrap();
+        assert!((temp_c - 21.5).abs() <= 1.0 / 128.0);
+        assert_eq!(rh_percent, 45.5);
+
+        ens160
+            .set_temp_rh_comp_fixed(CentiCelsius(2500), MilliPercent(50_000))
+            .unwrap();
+        assert_eq!(sim.borrow().temp_in, 19082);
+        assert_eq!(sim.borrow().rh_in, 0x6400);
+        let (temperature, humidity) = ens160.get_temp_rh_comp_fixed().unwrap();
+        assert_eq!(temperature, CentiCelsius(2501));
+        assert_eq!(humidity, MilliPercent(50_000));
+    }
+
+    #[test]
+    fn raw_resistances_f32_and_raw() {
+        let sim = RefCell::new(Ens160Sim::new(SimConfig::default(), &[]));
+        let mut ens160 = driver(&sim);
+        ens160.initialize().unwrap();
+        sim.borrow_mut().advance(2_000);
+        let raw = ens160.get_raw_resistances_raw().unwrap();
+        let ohms = ens160.get_raw_resistances().unwrap();
+        assert_ne!(raw[3], RawResistance(0));
+        for plate in 0..raw.len() {
+            assert_eq!(f32::from(raw[plate]), ohms[plate]);
+        }
+        // GPR_READ6 is hot plate 3
+        assert_eq!(ens160.get_raw_resistance_raw().unwrap(), raw[3]);
+        assert_eq!(ens160.get_raw_resistance().unwrap(), ohms[3]);
+    }
+
+    // simulated time is u64:  long runs pass the 49.7 days of u32 ms
+    #[test]
+    fn time_beyond_u32_ms() {
+        let start_ms = u32::MAX as u64 - 2_000;
+        let events = [
+            fault(start_ms + 4_000, SimFault::StatusError, 2_000),
+            SimEvent {
+                at_ms: start_ms + 4_000,
+                kind: SimEventKind::VocRelease {
+                    ppb: 500.0,
+                    decay_ms: 60_000.0,
+                },
+            },
+        ];
+        let sim = RefCell::new(Ens160Sim::new(SimConfig::default(), &events));
+        sim.borrow_mut().time_ms = start_ms;
+        let mut ens160 = driver(&sim);
+        ens160.initialize().unwrap();
+        sim.borrow_mut().advance(3_000);
+        assert!(sim.borrow().time_ms() > u32::MAX as u64);
+        assert!(!ens160.get_status().unwrap().error());
+        sim.borrow_mut().advance(1_000);
+        assert!(ens160.get_status().unwrap().error());
+        assert!(sim.borrow().true_tvoc_ppb() > 500.0);
+        sim.borrow_mut().advance(WARMUP_MS);
+        let status = ens160.get_status().unwrap();
+        assert!(!status.error());
+        assert!(status.new_data_ready());
+        assert_eq!(status.validity_flag(), ValidityFlag::NormalOperation);
+    }
+}
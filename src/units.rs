@@ -0,0 +1,237 @@
+// fixed-point units for the compensation and raw resistance registers, for MCUs without an
+// FPU.  The driver has _fixed / _raw variants of the compensation and resistance methods that
+// take and return these, the conversions use integer arithmetic only.
+
+/// temperature in 0.01 degrees C, e.g. CentiCelsius(2550) is 25.5 C
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct CentiCelsius(pub i32);
+
+impl CentiCelsius {
+    /// from a TEMP_IN / DATA_T register value (Kelvin * 64), rounded to 0.01 C
+    pub fn from_raw(raw: u16) -> Self {
+        // raw * 100 / 64 = raw * 25 / 16 centi Kelvin
+        CentiCelsius(((raw as i32 * 25 + 8) >> 4) - 27315)
+    }
+
+    /// TEMP_IN register value (Kelvin * 64), rounded and limited to the register range
+    pub fn to_raw(self) -> u16 {
+        let centi_kelvin = self.0.saturating_add(27315).max(0) as u64;
+        ((centi_kelvin * 16 + 12) / 25).min(u16::MAX as u64) as u16
+    }
+}
+
+/// relative humidity in 0.001 percent, e.g. MilliPercent(45500) is 45.5 %
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct MilliPercent(pub u32);
+
+impl MilliPercent {
+    /// from a RH_IN / DATA_RH register value (percent * 512), rounded to 0.001 %
+    pub fn from_raw(raw: u16) -> Self {
+        // raw * 1000 / 512 = raw * 125 / 64
+        MilliPercent((raw as u32 * 125 + 32) >> 6)
+    }
+
+    /// RH_IN register value (percent * 512), rounded and limited to the register range
+    pub fn to_raw(self) -> u16 {
+        let raw = (self.0 as u64 * 64 + 62) / 125;
+        raw.min(u16::MAX as u64) as u16
+    }
+}
+
+/// raw hot plate resistance in the datasheet's log2 encoding:  ohms = 2^(value / 2048)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct RawResistance(pub u16);
+
+impl RawResistance {
+    /// resistance in ohms, computed with integer arithmetic only.  Within 2.1 ppm of the
+    /// exact value rounded to ohms
+    pub fn ohms(self) -> u32 {
+        let (mantissa, integer) = self.exp2();
+        (((mantissa << integer) + (1 << 30)) >> 31) as u32
+    }
+
+    // 2^(value / 2048) = 2^integer * 2^(high 5 fraction bits / 32) * 2^(low 6 bits / 2048),
+    // returns the mantissa in [1, 2) with 31 fraction bits and the integer part
+    fn exp2(self) -> (u64, u32) {
+        let integer = (self.0 >> 11) as u32;
+        let high = ((self.0 >> 6) & 0x1f) as usize;
+        let low = (self.0 & 0x3f) as usize;
+        ((EXP2_HIGH[high] as u64 * EXP2_LOW[low] as u64) >> 31, integer)
+    }
+}
+
+impl From<RawResistance> for f32 {
+    /// resistance in ohms, see datasheet section 7
+    fn from(raw: RawResistance) -> Self {
+        let (mantissa, integer) = raw.exp2();
+        (mantissa << integer) as f32 / (1u64 << 31) as f32
+    }
+}
+
+// round a non-negative value to the nearest register value, negative values give 0 and
+// values above the register range u16::MAX (float to int casts saturate)
+fn round_to_raw(value: f32) -> u16 {
+    (value + 0.5) as u16
+}
+
+// compensation temperature in degrees C to TEMP_IN register value (Kelvin * 64), rounded
+pub(crate) fn temperature_to_raw(temp_c: f32) -> u16 {
+    round_to_raw((temp_c + 273.15) * 64.0)
+}
+
+// DATA_T register value (Kelvin * 64) to compensation temperature in degrees C
+pub(crate) fn temperature_from_raw(raw: u16) -> f32 {
+    (raw as f32 / 64.0) - 273.15
+}
+
+// compensation humidity in percent to RH_IN register value (percent * 512), rounded
+pub(crate) fn humidity_to_raw(rh_percent: f32) -> u16 {
+    round_to_raw(rh_percent * 512.0)
+}
+
+// DATA_RH register value (percent * 512) to compensation humidity in percent
+pub(crate) fn humidity_from_raw(raw: u16) -> f32 {
+    raw as f32 / 512.0
+}
+
+// GPR_READ register value to raw resistance in ohms
+pub(crate) fn resistance_from_raw(raw: u16) -> f32 {
+    RawResistance(raw).into()
+}
+
+// 2^(k / 32) for k in 0..32, 31 fraction bits
+#[rustfmt::skip]
+const EXP2_HIGH: [u32; 32] = [
+    0x80000000, 0x82cd8699, 0x85aac368, 0x88980e81,
+    0x8b95c1e4, 0x8ea4398b, 0x91c3d374, 0x94f4efa9,
+    0x9837f052, 0x9b8d39ba, 0x9ef53261, 0xa2704303,
+    0xa5fed6aa, 0xa9a15ab5, 0xad583eea, 0xb123f582,
+    0xb504f334, 0xb8fbaf47, 0xbd08a39f, 0xc12c4cca,
+    0xc5672a11, 0xc9b9bd86, 0xce248c15, 0xd2a81d92,
+    0xd744fccb, 0xdbfbb798, 0xe0ccdeec, 0xe5b906e7,
+    0xeac0c6e8, 0xefe4b99c, 0xf5257d15, 0xfa83b2db,
+];
+
+// 2^(k / 2048) for k in 0..64, 31 fraction bits
+#[rustfmt::skip]
+const EXP2_LOW: [u32; 64] = [
+    0x80000000, 0x800b179d, 0x8016302f, 0x802149b8,
+    0x802c6437, 0x80377fac, 0x80429c18, 0x804db97a,
+    0x8058d7d3, 0x8063f722, 0x806f1768, 0x807a38a5,
+    0x80855ad9, 0x80907e04, 0x809ba226, 0x80a6c73f,
+    0x80b1ed50, 0x80bd1458, 0x80c83c57, 0x80d3654d,
+    0x80de8f3c, 0x80e9ba21, 0x80f4e5ff, 0x810012d4,
+    0x810b40a2, 0x81166f67, 0x81219f25, 0x812ccfda,
+    0x81380188, 0x8143342e, 0x814e67cd, 0x81599c64,
+    0x8164d1f4, 0x8170087c, 0x817b3ffd, 0x81867877,
+    0x8191b1ea, 0x819cec56, 0x81a827bb, 0x81b36419,
+    0x81bea171, 0x81c9dfc1, 0x81d51f0c, 0x81e05f4f,
+    0x81eba08d, 0x81f6e2c4, 0x820225f4, 0x820d6a1f,
+    0x8218af43, 0x8223f562, 0x822f3c7b, 0x823a848e,
+    0x8245cd9b, 0x825117a2, 0x825c62a4, 0x8267aea1,
+    0x8272fb98, 0x827e4989, 0x82899876, 0x8294e85d,
+    0x82a03940, 0x82ab8b1d, 0x82b6ddf6, 0x82c231ca,
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exp2_tables() {
+        for (k, entry) in EXP2_HIGH.iter().enumerate() {
+            let exact = (2f64.powf(k as f64 / 32.0) * (1u64 << 31) as f64).round();
+            assert_eq!(*entry as f64, exact, "EXP2_HIGH[{}]", k);
+        }
+        for (k, entry) in EXP2_LOW.iter().enumerate() {
+            let exact = (2f64.powf(k as f64 / 2048.0) * (1u64 << 31) as f64).round();
+            assert_eq!(*entry as f64, exact, "EXP2_LOW[{}]", k);
+        }
+    }
+
+    #[test]
+    fn raw_resistance_ohms_all_codes() {
+        for value in 0..=u16::MAX {
+            let exact = 2f64.powf(value as f64 / 2048.0);
+            let ohms = RawResistance(value).ohms() as f64;
+            // the table arithmetic adds less than 1 ppb to the final rounding
+            assert!(
+                (ohms - exact).abs() <= 0.5 + exact * 1.0e-9,
+                "code {}: {} ohm, exact {}",
+                value,
+                ohms,
+                exact
+            );
+            assert!(
+                (ohms - exact.round()).abs() <= exact * 2.11e-6,
+                "code {}",
+                value
+            );
+        }
+        assert_eq!(RawResistance(0).ohms(), 1);
+        assert_eq!(RawResistance(2048 * 16).ohms(), 65536);
+    }
+
+    #[test]
+    fn centi_celsius_round_trip() {
+        // every register value survives, 0.01 C is finer than the 1/64 K resolution
+        for raw in 0..=u16::MAX {
+            assert_eq!(CentiCelsius::from_raw(raw).to_raw(), raw, "raw {}", raw);
+        }
+        for centi in -4000..=8500 {
+            let round_trip = CentiCelsius::from_raw(CentiCelsius(centi).to_raw());
+            assert!((round_trip.0 - centi).abs() <= 1, "{} C/100", centi);
+        }
+        // datasheet encoding:  Kelvin * 64
+        assert_eq!(CentiCelsius(2500).to_raw(), 19082);
+        assert_eq!(CentiCelsius::from_raw(19082), CentiCelsius(2501));
+        assert_eq!(CentiCelsius(-27315).to_raw(), 0);
+        assert_eq!(CentiCelsius(-30000).to_raw(), 0);
+        assert_eq!(CentiCelsius(i32::MAX).to_raw(), u16::MAX);
+    }
+
+    #[test]
+    fn milli_percent_round_trip() {
+        for raw in 0..=u16::MAX {
+            // 0.001 % is finer than the 1/512 % resolution
+            let milli_percent = MilliPercent::from_raw(raw);
+            assert_eq!(milli_percent.to_raw(), raw, "raw {}", raw);
+        }
+        for milli in 0..=100_000 {
+            let round_trip = MilliPercent::from_raw(MilliPercent(milli).to_raw());
+            assert!(round_trip.0.abs_diff(milli) <= 1, "{} %/1000", milli);
+        }
+        // datasheet encoding:  percent * 512
+        assert_eq!(MilliPercent(50_000).to_raw(), 0x6400);
+        assert_eq!(MilliPercent::from_raw(0x6400), MilliPercent(50_000));
+        assert_eq!(MilliPercent(u32::MAX).to_raw(), u16::MAX);
+    }
+
+    // the f32 and fixed-point conversions write the same register values
+    #[test]
+    fn compensation_register_values() {
+        assert_eq!(temperature_to_raw(25.0), 19082);
+        assert_eq!(CentiCelsius(2500).to_raw(), 19082);
+        assert_eq!(temperature_to_raw(temperature_from_raw(19082)), 19082);
+        assert_eq!(humidity_to_raw(50.0), 0x6400);
+        assert_eq!(MilliPercent(50_000).to_raw(), 0x6400);
+        assert_eq!(humidity_from_raw(0x6400), 50.0);
+
+        assert_eq!(humidity_to_raw(45.5), 23296);
+        assert_eq!(humidity_to_raw(-1.0), 0);
+        assert_eq!(humidity_to_raw(200.0), u16::MAX);
+        assert_eq!(humidity_from_raw(1), 1.0 / 512.0);
+        assert_eq!(temperature_to_raw(21.5), 18858);
+        assert_eq!(temperature_to_raw(-300.0), 0);
+    }
+
+    #[test]
+    fn raw_resistance_f32() {
+        for value in (0..=u16::MAX).step_by(7) {
+            let exact = 2f64.powf(value as f64 / 2048.0);
+            let ohms = f32::from(RawResistance(value)) as f64;
+            assert!((ohms - exact).abs() <= exact * 1.0e-7, "code {}", value);
+        }
+        assert_eq!(resistance_from_raw(2048 * 16), 65536.0);
+    }
+}